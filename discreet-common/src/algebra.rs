@@ -1,28 +1,58 @@
 use std::iter::repeat_n;
 
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::Ident;
 
 use crate::taylor::DerivativeApproximations;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Variable {
     X,
     Y,
+    Z,
 }
 
 impl Variable {
-    pub fn from_char(c: char) -> Option<Self> {
-        match c {
-            'x' => Some(Self::X),
-            'y' => Some(Self::Y),
+    /// The variable corresponding to the given mesh index, i.e. `X` for the first index (`i`), `Y` for the
+    /// second (`j`) and `Z` for the third (`k`).
+    pub fn from_index(index: usize) -> Option<Self> {
+        match index {
+            0 => Some(Self::X),
+            1 => Some(Self::Y),
+            2 => Some(Self::Z),
             _ => None,
         }
     }
+
+    pub fn index(&self) -> usize {
+        match self {
+            Self::X => 0,
+            Self::Y => 1,
+            Self::Z => 2,
+        }
+    }
+
+    /// The lowercase name of the variable, as used in the names of values in generated code.
+    pub fn name(&self) -> char {
+        match self {
+            Self::X => 'x',
+            Self::Y => 'y',
+            Self::Z => 'z',
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+/// The names of the indices of a mesh with the given number of dimensions in generated code, i.e. `i`, `j`
+/// and `k`.
+pub fn index_idents(dimensions: usize) -> Vec<Ident> {
+    ["i", "j", "k"][..dimensions]
+        .iter()
+        .map(|name| format_ident!("{name}"))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Expression {
     Prod(Vec<Expression>),
     Sum(Vec<Expression>),
@@ -33,12 +63,16 @@ pub enum Expression {
     CrossDerivative(Vec<Variable>),
     Negate(Box<Expression>),
     Reciprocal(Box<Expression>),
+    /// An expression raised to a constant power.
+    Pow(Box<Expression>, f64),
 }
 
 impl Expression {
-    pub fn list_required_derivatives(&self) -> Vec<(Variable, usize)> {
+    /// Lists the derivatives in the expression by the variables they are taken with respect to, as used
+    /// for keys in [`DerivativeApproximations`].
+    pub fn list_required_derivatives(&self) -> Vec<Vec<Variable>> {
         match self {
-            Self::Derivative(v, o) => vec![(*v, *o)],
+            Self::Derivative(v, o) => vec![vec![*v; *o]],
             Self::Sum(vec) | Self::Prod(vec) => {
                 let mut items = Vec::new();
                 for item in vec {
@@ -47,12 +81,32 @@ impl Expression {
                 }
                 items
             }
-            Self::CrossDerivative(_) => todo!(),
-            Self::Negate(e) | Self::Reciprocal(e) => e.list_required_derivatives(),
+            Self::CrossDerivative(vars) => {
+                let mut vars = vars.clone();
+                vars.sort();
+                vec![vars]
+            }
+            Self::Negate(e) | Self::Reciprocal(e) | Self::Pow(e, _) => {
+                e.list_required_derivatives()
+            }
             _ => vec![],
         }
     }
 
+    /// Evaluates the expression numerically. `leaf` gives the values of the solution, derivatives and
+    /// symbolic constants.
+    pub fn evaluate<F: Fn(&Self) -> f64>(&self, leaf: &F) -> f64 {
+        match self {
+            Self::Sum(items) => items.iter().map(|e| e.evaluate(leaf)).sum(),
+            Self::Prod(items) => items.iter().map(|e| e.evaluate(leaf)).product(),
+            Self::Constant(c) => *c,
+            Self::Negate(e) => -e.evaluate(leaf),
+            Self::Reciprocal(e) => 1. / e.evaluate(leaf),
+            Self::Pow(e, n) => e.evaluate(leaf).powf(*n),
+            other => leaf(other),
+        }
+    }
+
     pub fn substitute<F: Fn(&Self) -> Option<Self>>(self, func: &F) -> Self {
         match func(&self) {
             Some(expr) => expr,
@@ -71,6 +125,7 @@ impl Expression {
             Self::CrossDerivative(_) => self,
             Self::Negate(e) => Self::Negate(Box::new(func(*e))),
             Self::Reciprocal(e) => Self::Reciprocal(Box::new(func(*e))),
+            Self::Pow(e, n) => Self::Pow(Box::new(func(*e)), n),
         }
     }
 }
@@ -78,7 +133,9 @@ impl Expression {
 /// An expression in terms of values on the mesh
 #[derive(Clone, Debug, PartialEq)]
 pub enum MeshExpr {
-    AtOffset(isize, isize),
+    /// The value of the solution at the node offset from the center of the stencil by the given number of
+    /// nodes along each index.
+    AtOffset(Vec<isize>),
     Prod(Vec<MeshExpr>),
     Sum(Vec<MeshExpr>),
     Constant(f64),
@@ -86,13 +143,37 @@ pub enum MeshExpr {
     FunctionVal(Ident),
     Negate(Box<MeshExpr>),
     Reciprocal(Box<MeshExpr>),
+    Pow(Box<MeshExpr>, f64),
+    /// The spacing of the mesh along a variable, rendered as `dx` or `dy`.
+    Spacing(Variable),
+    /// A value that doesn't depend on the node, which is computed once before iterating over the mesh and
+    /// rendered as `scale_const_N`. See [`MeshExpr::hoist_constants`].
+    ScaleConst(usize),
+    /// A derivative of a computational coordinate (`p` for [`Variable::X`], `q` for [`Variable::Y`], `r` for
+    /// [`Variable::Z`]) with respect to the physical coordinates, at the node the scheme is centered on.
+    /// Rendered as e.g. `metric_p_x` or `metric_q_xy`, which are read from the mesh before evaluating the
+    /// scheme.
+    Metric(Variable, Vec<Variable>),
+    /// The weight of the node at an offset along a variable in the approximation of the derivative of the
+    /// given order, on a rectilinear mesh where the weights depend on the local spacing. Rendered as e.g.
+    /// `weight_x2_m1` for the node at offset -1 in the second derivative w.r.t. x, which are read from the
+    /// mesh before evaluating the scheme.
+    Weight(Variable, usize, isize),
 }
 
 impl MeshExpr {
+    /// The value of the solution at the center of a stencil with the given number of dimensions.
+    pub fn center(dimensions: usize) -> Self {
+        Self::AtOffset(vec![0; dimensions])
+    }
+
+    /// Converts an equation into an expression in terms of the values on a mesh with the given number of
+    /// dimensions, using the given approximations of its derivatives.
     pub fn from_diff_eq(
         eq: Expression,
         fns: &[String],
         derivatives: &DerivativeApproximations,
+        dimensions: usize,
     ) -> Result<Self, String> {
         match eq {
             Expression::Constant(c) => Ok(Self::Constant(c)),
@@ -100,7 +181,7 @@ impl MeshExpr {
                 let mut new_terms = Vec::with_capacity(terms.len());
 
                 for term in terms.into_iter() {
-                    new_terms.push(Self::from_diff_eq(term, fns, derivatives)?)
+                    new_terms.push(Self::from_diff_eq(term, fns, derivatives, dimensions)?)
                 }
 
                 Ok(Self::Sum(new_terms))
@@ -109,32 +190,58 @@ impl MeshExpr {
                 let mut new_factors = Vec::with_capacity(factors.len());
 
                 for term in factors.into_iter() {
-                    new_factors.push(Self::from_diff_eq(term, fns, derivatives)?)
+                    new_factors.push(Self::from_diff_eq(term, fns, derivatives, dimensions)?)
                 }
 
-                Ok(Self::Sum(new_factors))
+                Ok(Self::Prod(new_factors))
             }
             Expression::SymbolicConstant(c) => Ok(if fns.contains(&format!("{c}")) {
                 MeshExpr::FunctionVal(c)
             } else {
                 MeshExpr::SymbolicConst(c)
             }),
-            Expression::Derivative(v, o) => match derivatives.get(&(v, o)) {
+            Expression::Derivative(v, o) => match derivatives.get(&vec![v; o]) {
                 Some(d) => Ok(d.clone()),
                 None => Err("Unknown derivative.".into()),
             },
-            Expression::CrossDerivative(_) => todo!(),
-            Expression::SolutionVal => Ok(Self::AtOffset(0, 0)),
+            Expression::CrossDerivative(mut vars) => {
+                vars.sort();
+                match derivatives.get(&vars) {
+                    Some(d) => Ok(d.clone()),
+                    None => Err("Unknown derivative.".into()),
+                }
+            }
+            Expression::SolutionVal => Ok(Self::center(dimensions)),
             Expression::Negate(e) => Ok(Self::Negate(Box::new(Self::from_diff_eq(
                 *e,
                 fns,
                 derivatives,
+                dimensions,
             )?))),
             Expression::Reciprocal(e) => Ok(Self::Reciprocal(Box::new(Self::from_diff_eq(
                 *e,
                 fns,
                 derivatives,
+                dimensions,
             )?))),
+            Expression::Pow(e, n) => Ok(Self::Pow(
+                Box::new(Self::from_diff_eq(*e, fns, derivatives, dimensions)?),
+                n,
+            )),
+        }
+    }
+
+    /// Evaluates the expression numerically. `leaf` gives the values of mesh values, constants, functions
+    /// and spacings.
+    pub fn evaluate<F: Fn(&Self) -> f64>(&self, leaf: &F) -> f64 {
+        match self {
+            Self::Sum(items) => items.iter().map(|e| e.evaluate(leaf)).sum(),
+            Self::Prod(items) => items.iter().map(|e| e.evaluate(leaf)).product(),
+            Self::Constant(c) => *c,
+            Self::Negate(e) => -e.evaluate(leaf),
+            Self::Reciprocal(e) => 1. / e.evaluate(leaf),
+            Self::Pow(e, n) => e.evaluate(leaf).powf(*n),
+            other => leaf(other),
         }
     }
 
@@ -153,6 +260,16 @@ impl MeshExpr {
 
                     lhs.product_rule(&rhs, variable)
                 }
+                Self::Pow(base, n) => Self::Prod(vec![
+                    Self::Constant(*n),
+                    Self::Pow(base.clone(), n - 1.),
+                    base.differentiate(variable),
+                ]),
+                Self::Negate(e) => Self::Negate(Box::new(e.differentiate(variable))),
+                Self::Reciprocal(e) => Self::Prod(vec![
+                    Self::Negate(Box::new(e.differentiate(variable))),
+                    Self::Reciprocal(Box::new(Self::Pow(e.clone(), 2.))),
+                ]),
                 _ => Self::Constant(0.),
             }
         }
@@ -177,6 +294,138 @@ impl MeshExpr {
         .simplify()
     }
 
+    /// Whether the expression has the same value at every node of the mesh, i.e. it doesn't contain any
+    /// values of the solution or of functions.
+    pub fn is_point_independent(&self) -> bool {
+        match self {
+            Self::AtOffset(_) | Self::FunctionVal(_) | Self::Metric(..) | Self::Weight(..) => false,
+            Self::Sum(items) | Self::Prod(items) => items.iter().all(Self::is_point_independent),
+            Self::Negate(e) | Self::Reciprocal(e) | Self::Pow(e, _) => e.is_point_independent(),
+            Self::Constant(_) | Self::SymbolicConst(_) | Self::Spacing(_) | Self::ScaleConst(_) => {
+                true
+            }
+        }
+    }
+
+    /// Lists the offsets of the solution values used in the expression, without duplicates.
+    pub fn offsets(&self) -> Vec<Vec<isize>> {
+        let mut offsets = Vec::new();
+        self.collect_offsets(&mut offsets);
+        offsets
+    }
+
+    fn collect_offsets(&self, offsets: &mut Vec<Vec<isize>>) {
+        match self {
+            Self::AtOffset(offset) if !offsets.contains(offset) => {
+                offsets.push(offset.clone());
+            }
+            Self::Sum(items) | Self::Prod(items) => {
+                for item in items {
+                    item.collect_offsets(offsets);
+                }
+            }
+            Self::Negate(e) | Self::Reciprocal(e) | Self::Pow(e, _) => e.collect_offsets(offsets),
+            _ => {}
+        }
+    }
+
+    /// The derivatives whose [`MeshExpr::Weight`]s the expression uses, as the variable and the order.
+    pub fn weights(&self) -> Vec<(Variable, usize)> {
+        let mut weights = Vec::new();
+        self.collect_weights(&mut weights);
+        weights
+    }
+
+    fn collect_weights(&self, weights: &mut Vec<(Variable, usize)>) {
+        match self {
+            &Self::Weight(variable, order, _) if !weights.contains(&(variable, order)) => {
+                weights.push((variable, order));
+            }
+            Self::Sum(items) | Self::Prod(items) => {
+                for item in items {
+                    item.collect_weights(weights);
+                }
+            }
+            Self::Negate(e) | Self::Reciprocal(e) | Self::Pow(e, _) => e.collect_weights(weights),
+            _ => {}
+        }
+    }
+
+    /// If the expression is linear in the solution values, rearranges it into the form
+    /// `a * u(i-1, j) + b * u(i, j-1) + ... + rest`, where the coefficients don't depend on the solution.
+    /// This allows coefficients that don't depend on the node either to be computed once for the whole mesh.
+    /// Returns the expression simplified if it isn't linear.
+    pub fn collect_linear(self) -> Self {
+        let offsets = self.offsets();
+
+        let mut terms = Vec::with_capacity(offsets.len() + 1);
+        for offset in &offsets {
+            let node = Self::AtOffset(offset.clone());
+            let coefficient = self.differentiate(&node).simplify();
+            if !coefficient.offsets().is_empty() {
+                return self.simplify();
+            }
+
+            terms.push(Self::Prod(vec![coefficient, node]));
+        }
+
+        let rest = offsets.into_iter().fold(self, |expr, offset| {
+            expr.substitute(&Self::AtOffset(offset), &Self::Constant(0.))
+        });
+        terms.push(rest);
+
+        Self::Sum(terms).simplify()
+    }
+
+    /// Replaces the parts of the expression that are the same at every node by [`MeshExpr::ScaleConst`]s,
+    /// so that they only need to be computed once. The replaced expressions are appended to `hoisted`, with
+    /// their index being the index of the `ScaleConst`.
+    pub fn hoist_constants(self, hoisted: &mut Vec<MeshExpr>) -> Self {
+        match self {
+            Self::Constant(_) | Self::ScaleConst(_) => self,
+            e if e.is_point_independent() => {
+                hoisted.push(e);
+                Self::ScaleConst(hoisted.len() - 1)
+            }
+            Self::Sum(items) => Self::Sum(Self::hoist_from_items(items, hoisted, Self::Sum)),
+            Self::Prod(items) => Self::Prod(Self::hoist_from_items(items, hoisted, Self::Prod)),
+            Self::Negate(e) => Self::Negate(Box::new(e.hoist_constants(hoisted))),
+            Self::Reciprocal(e) => Self::Reciprocal(Box::new(e.hoist_constants(hoisted))),
+            Self::Pow(e, n) => Self::Pow(Box::new(e.hoist_constants(hoisted)), n),
+            other => other,
+        }
+    }
+
+    /// Hoists the point independent items of a sum or product together, as a single constant.
+    fn hoist_from_items<F: Fn(Vec<Self>) -> Self>(
+        items: Vec<Self>,
+        hoisted: &mut Vec<MeshExpr>,
+        combine: F,
+    ) -> Vec<Self> {
+        let (independent, dependent): (Vec<_>, Vec<_>) =
+            items.into_iter().partition(Self::is_point_independent);
+
+        let mut items: Vec<_> = dependent
+            .into_iter()
+            .map(|e| e.hoist_constants(hoisted))
+            .collect();
+
+        match independent.len() {
+            0 => {}
+            1 => items.insert(
+                0,
+                independent
+                    .into_iter()
+                    .next()
+                    .unwrap()
+                    .hoist_constants(hoisted),
+            ),
+            _ => items.insert(0, combine(independent).hoist_constants(hoisted)),
+        }
+
+        items
+    }
+
     pub fn substitute(self, target: &MeshExpr, replacement: &MeshExpr) -> Self {
         if &self == target {
             return replacement.clone();
@@ -184,6 +433,7 @@ impl MeshExpr {
         match self {
             Self::Negate(e) => Self::Negate(Box::new(e.substitute(target, replacement))),
             Self::Reciprocal(e) => Self::Reciprocal(Box::new(e.substitute(target, replacement))),
+            Self::Pow(e, n) => Self::Pow(Box::new(e.substitute(target, replacement)), n),
             Self::Sum(items) => Self::Sum(
                 items
                     .into_iter()
@@ -203,79 +453,178 @@ impl MeshExpr {
     pub fn simplify(self) -> Self {
         match self {
             Self::Sum(items) => {
-                let mut items: Vec<_> = items
-                    .into_iter()
-                    .map(|e| e.simplify())
-                    .filter(|e| e != &Self::Constant(0.))
-                    .collect();
-
-                let mut new_items = Vec::new();
+                let mut constant = 0.;
+                let mut new_items = Vec::with_capacity(items.len());
 
-                for item in items {
+                for item in items.into_iter().map(Self::simplify) {
                     match item {
-                        Self::Sum(mut inner) => new_items.append(&mut inner),
+                        Self::Sum(inner) => new_items.extend(inner),
+                        Self::Constant(c) => constant += c,
                         other => new_items.push(other),
                     }
                 }
 
-                items = new_items;
+                if constant != 0. {
+                    new_items.push(Self::Constant(constant));
+                }
 
-                if items.len() == 1 {
-                    items.into_iter().next().unwrap()
+                if new_items.len() == 1 {
+                    new_items.into_iter().next().unwrap()
+                } else if new_items.is_empty() {
+                    Self::Constant(0.)
                 } else {
-                    Self::Sum(items)
+                    Self::Sum(new_items)
                 }
             }
             Self::Prod(items) => {
-                let items: Vec<_> = items
-                    .into_iter()
-                    .map(|e| e.simplify())
-                    .filter(|e| e != &Self::Constant(1.))
-                    .collect();
+                let mut constant = 1.;
+                let mut new_items = Vec::with_capacity(items.len());
+
+                for item in items.into_iter().map(Self::simplify) {
+                    match item {
+                        Self::Prod(inner) => new_items.extend(inner),
+                        Self::Constant(c) => constant *= c,
+                        other => new_items.push(other),
+                    }
+                }
 
-                if items.len() == 1 {
-                    items.into_iter().next().unwrap()
-                } else if items.is_empty() {
+                if constant == 0. {
+                    return Self::Constant(0.);
+                } else if constant == -1. && !new_items.is_empty() {
+                    return Self::Negate(Box::new(Self::Prod(new_items).simplify()));
+                } else if constant != 1. {
+                    new_items.insert(0, Self::Constant(constant));
+                }
+
+                if new_items.len() == 1 {
+                    new_items.into_iter().next().unwrap()
+                } else if new_items.is_empty() {
                     Self::Constant(1.)
-                } else if items.contains(&Self::Constant(0.)) {
-                    Self::Constant(0.)
                 } else {
-                    Self::Prod(items)
+                    Self::Prod(new_items)
                 }
             }
-            Self::Negate(n) if *n == Self::Constant(0.) => Self::Constant(0.),
-            Self::Reciprocal(n) if *n == Self::Constant(1.) => Self::Constant(1.),
-            Self::Negate(e) => Self::Negate(Box::new(e.simplify())),
-            Self::Reciprocal(e) => Self::Reciprocal(Box::new(e.simplify())),
+            Self::Negate(e) => match e.simplify() {
+                Self::Constant(c) => Self::Constant(-c),
+                Self::Negate(inner) => *inner,
+                Self::Prod(mut items) => match items.first_mut() {
+                    Some(Self::Constant(c)) => {
+                        *c = -*c;
+                        Self::Prod(items).simplify()
+                    }
+                    _ => Self::Negate(Box::new(Self::Prod(items))),
+                },
+                other => Self::Negate(Box::new(other)),
+            },
+            Self::Reciprocal(e) => match e.simplify() {
+                Self::Constant(c) if c != 0. => Self::Constant(1. / c),
+                Self::Reciprocal(inner) => *inner,
+                other => Self::Reciprocal(Box::new(other)),
+            },
+            Self::Pow(_, 0.) => Self::Constant(1.),
+            Self::Pow(e, 1.) => e.simplify(),
+            Self::Pow(e, n) => match e.simplify() {
+                Self::Constant(c) => Self::Constant(c.powf(n)),
+                base => Self::Pow(Box::new(base), n),
+            },
             other => other,
         }
     }
 
+    /// Renders the expression as code for a scheme on a 2D mesh, centered at node `(i, j)`.
     pub fn render(&self) -> TokenStream {
+        self.render_in(2)
+    }
+
+    /// Renders the expression as code for a scheme on a mesh with the given number of dimensions, centered
+    /// at the node given by the indices from [`index_idents`].
+    pub fn render_in(&self, dimensions: usize) -> TokenStream {
+        let indices = index_idents(dimensions);
+        self.render_with(dimensions, &|offset| {
+            quote! {self.mesh.get_at_offset(#(#indices,)* #(#offset),*)}
+        })
+    }
+
+    /// Renders the expression like [`Self::render_in`], but reading the values of the solution from the
+    /// slice `values`, which is indexed like the values of the mesh. This allows evaluating the scheme on
+    /// states that aren't stored in the mesh, such as the stages of a Runge-Kutta method.
+    pub fn render_from(&self, dimensions: usize, values: &Ident) -> TokenStream {
+        let indices = index_idents(dimensions);
+        self.render_with(dimensions, &|offset| {
+            quote! {#values[self.mesh.offset_index(#(#indices,)* #(#offset),*)]}
+        })
+    }
+
+    /// Renders the expression, with the values of the solution rendered by `at_offset`.
+    fn render_with(
+        &self,
+        dimensions: usize,
+        at_offset: &dyn Fn(&[isize]) -> TokenStream,
+    ) -> TokenStream {
+        let indices = index_idents(dimensions);
+
         match self {
-            &Self::AtOffset(i, j) => {
-                quote! {self.mesh.get_at((i as isize + (#i)) as usize, (j as isize + (#j)) as usize)}
-            }
+            Self::AtOffset(offset) => at_offset(offset),
             &Self::Constant(c) => quote! {#c},
-            Self::FunctionVal(_f) => todo!(),
+            Self::FunctionVal(f) => quote! {self.fns.#f[self.mesh.get_index(#(#indices),*)]},
             Self::Negate(expr) => {
-                let expr = expr.render();
+                let expr = expr.render_with(dimensions, at_offset);
                 quote! {(-#expr)}
             }
             Self::Reciprocal(expr) => {
-                let expr = expr.render();
+                let expr = expr.render_with(dimensions, at_offset);
                 quote! {(1. / #expr)}
             }
+            &Self::Pow(ref expr, n) => {
+                let expr = expr.render_with(dimensions, at_offset);
+                if n.fract() == 0. && n.abs() <= i32::MAX as f64 {
+                    let n = n as i32;
+                    quote! {(#expr).powi(#n)}
+                } else {
+                    quote! {(#expr).powf(#n)}
+                }
+            }
             Self::SymbolicConst(c) => quote! {self.consts.#c},
+            Self::Spacing(variable) => {
+                let ident = format_ident!("d{}", variable.name());
+                quote! {#ident}
+            }
+            &Self::ScaleConst(k) => {
+                let ident = format_ident!("scale_const_{k}");
+                quote! {#ident}
+            }
+            Self::Metric(coordinate, vars) => {
+                let coordinate = match coordinate {
+                    Variable::X => "p",
+                    Variable::Y => "q",
+                    Variable::Z => "r",
+                };
+                let vars: String = vars.iter().map(Variable::name).collect();
+
+                let ident = format_ident!("metric_{coordinate}_{vars}");
+                quote! {#ident}
+            }
+            &Self::Weight(variable, order, offset) => {
+                let ident = weight_ident(variable, order, offset);
+                quote! {#ident}
+            }
             Self::Sum(items) => {
                 let mut iter = items.iter();
 
-                let first = iter.next().unwrap().render();
+                let first = iter.next().unwrap().render_with(dimensions, at_offset);
                 let mut stream = quote! {#first};
 
                 for item in iter {
-                    let rendered = item.render();
-                    stream = quote! {#stream + #rendered}
+                    stream = match item {
+                        Self::Negate(e) => {
+                            let rendered = e.render_with(dimensions, at_offset);
+                            quote! {#stream - #rendered}
+                        }
+                        _ => {
+                            let rendered = item.render_with(dimensions, at_offset);
+                            quote! {#stream + #rendered}
+                        }
+                    }
                 }
 
                 quote! {(#stream)}
@@ -283,12 +632,20 @@ impl MeshExpr {
             Self::Prod(items) => {
                 let mut iter = items.iter();
 
-                let first = iter.next().unwrap().render();
+                let first = iter.next().unwrap().render_with(dimensions, at_offset);
                 let mut stream = quote! {#first};
 
                 for item in iter {
-                    let rendered = item.render();
-                    stream = quote! {#stream * #rendered}
+                    stream = match item {
+                        Self::Reciprocal(e) => {
+                            let rendered = e.render_with(dimensions, at_offset);
+                            quote! {#stream / #rendered}
+                        }
+                        _ => {
+                            let rendered = item.render_with(dimensions, at_offset);
+                            quote! {#stream * #rendered}
+                        }
+                    }
                 }
 
                 quote! {(#stream)}
@@ -297,6 +654,14 @@ impl MeshExpr {
     }
 }
 
+/// The name that a [`MeshExpr::Weight`] is rendered as, e.g. `weight_x2_m1`.
+pub fn weight_ident(variable: Variable, order: usize, offset: isize) -> Ident {
+    let variable = variable.name();
+    let sign = if offset < 0 { "m" } else { "" };
+
+    format_ident!("weight_{variable}{order}_{sign}{}", offset.unsigned_abs())
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct SquareMat {
     size: usize,
@@ -325,10 +690,30 @@ impl SquareMat {
         Self { size, vals }
     }
 
-    pub fn invert(mut self) -> Self {
+    /// Inverts the matrix using Gauss-Jordan elimination with partial pivoting.
+    ///
+    /// # Panics
+    /// If the matrix is singular.
+    pub fn invert(self) -> Self {
+        self.try_invert()
+            .expect("Tried to invert a singular matrix.")
+    }
+
+    /// Inverts the matrix, returning `None` if it is singular.
+    pub fn try_invert(mut self) -> Option<Self> {
         let mut inverse = Self::ident(self.size);
 
         for i in 0..self.size {
+            let pivot = (i..self.size)
+                .max_by(|&a, &b| self.get_at(a, i).abs().total_cmp(&self.get_at(b, i).abs()))?;
+            if self.get_at(pivot, i).abs() < 1e-12 {
+                return None;
+            }
+            if pivot != i {
+                inverse.row_swap(i, pivot);
+                self.row_swap(i, pivot);
+            }
+
             inverse.row_scale(i, 1. / self.get_at(i, i));
             self.row_scale(i, 1. / self.get_at(i, i));
 
@@ -341,7 +726,16 @@ impl SquareMat {
             }
         }
 
-        inverse
+        Some(inverse)
+    }
+
+    pub fn row_swap(&mut self, a: usize, b: usize) {
+        for i in 0..self.size {
+            let a_idx = Self::get_index(self.size, a, i);
+            let b_idx = Self::get_index(self.size, b, i);
+
+            self.vals.swap(a_idx, b_idx);
+        }
     }
 
     pub fn row_scale(&mut self, target_row: usize, factor: f64) {
@@ -388,6 +782,8 @@ impl SquareMat {
 
 #[cfg(test)]
 mod test {
+    use quote::format_ident;
+
     use crate::algebra::Variable;
 
     use super::{Expression, MeshExpr, SquareMat};
@@ -454,13 +850,202 @@ mod test {
         )
     }
 
+    #[test]
+    fn power_rule() {
+        let expr = MeshExpr::Pow(Box::new(MeshExpr::AtOffset(vec![0, 0])), 3.);
+
+        assert_eq!(
+            expr.differentiate(&MeshExpr::AtOffset(vec![0, 0]))
+                .simplify(),
+            MeshExpr::Prod(vec![
+                MeshExpr::Constant(3.),
+                MeshExpr::Pow(Box::new(MeshExpr::AtOffset(vec![0, 0])), 2.),
+            ]),
+        );
+    }
+
+    #[test]
+    fn collect_and_hoist() {
+        let c = MeshExpr::SymbolicConst(format_ident!("c"));
+        let dx = MeshExpr::Reciprocal(Box::new(MeshExpr::Spacing(Variable::X)));
+
+        // c / dx * (u(0, 0) - u(-1, 0)) - u(0, 0)
+        let expr = MeshExpr::Sum(vec![
+            MeshExpr::Prod(vec![
+                c.clone(),
+                dx.clone(),
+                MeshExpr::Sum(vec![
+                    MeshExpr::AtOffset(vec![0, 0]),
+                    MeshExpr::Negate(Box::new(MeshExpr::AtOffset(vec![-1, 0]))),
+                ]),
+            ]),
+            MeshExpr::Negate(Box::new(MeshExpr::AtOffset(vec![0, 0]))),
+        ]);
+
+        let mut hoisted = Vec::new();
+        let collected = expr.collect_linear().hoist_constants(&mut hoisted);
+
+        assert_eq!(
+            collected,
+            MeshExpr::Sum(vec![
+                MeshExpr::Prod(vec![
+                    MeshExpr::ScaleConst(0),
+                    MeshExpr::AtOffset(vec![0, 0])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::ScaleConst(1),
+                    MeshExpr::AtOffset(vec![-1, 0])
+                ]),
+            ])
+        );
+        assert_eq!(hoisted.len(), 2);
+        assert!(hoisted.iter().all(MeshExpr::is_point_independent));
+    }
+
     #[test]
     fn product_rule() {
-        let expr = MeshExpr::Prod(vec![MeshExpr::AtOffset(0, 0), MeshExpr::AtOffset(0, 0)]);
+        let expr = MeshExpr::Prod(vec![
+            MeshExpr::AtOffset(vec![0, 0]),
+            MeshExpr::AtOffset(vec![0, 0]),
+        ]);
 
         assert_eq!(
-            expr.differentiate(&MeshExpr::AtOffset(0, 0)).simplify(),
-            MeshExpr::Sum(vec![MeshExpr::AtOffset(0, 0), MeshExpr::AtOffset(0, 0),]),
+            expr.differentiate(&MeshExpr::AtOffset(vec![0, 0]))
+                .simplify(),
+            MeshExpr::Sum(vec![
+                MeshExpr::AtOffset(vec![0, 0]),
+                MeshExpr::AtOffset(vec![0, 0]),
+            ]),
         );
     }
+
+    mod properties {
+        use std::collections::HashMap;
+
+        use proptest::prelude::*;
+        use quote::format_ident;
+
+        use crate::algebra::{Expression, MeshExpr, Variable};
+
+        const SYMBOLS: [&str; 3] = ["a", "b", "f"];
+
+        fn expression() -> impl Strategy<Value = Expression> {
+            let leaf = prop_oneof![
+                (-4i32..5).prop_map(|c| Expression::Constant(c as f64 * 0.75)),
+                Just(Expression::SolutionVal),
+                (0..SYMBOLS.len())
+                    .prop_map(|k| Expression::SymbolicConstant(format_ident!("{}", SYMBOLS[k]))),
+                Just(Expression::Derivative(Variable::X, 1)),
+                Just(Expression::Derivative(Variable::Y, 2)),
+                Just(Expression::CrossDerivative(vec![Variable::Y, Variable::X])),
+            ];
+
+            leaf.prop_recursive(4, 32, 4, |inner| {
+                prop_oneof![
+                    prop::collection::vec(inner.clone(), 2..4).prop_map(Expression::Sum),
+                    prop::collection::vec(inner.clone(), 2..4).prop_map(Expression::Prod),
+                    inner.clone().prop_map(|e| Expression::Negate(Box::new(e))),
+                    inner
+                        .clone()
+                        .prop_map(|e| Expression::Reciprocal(Box::new(e))),
+                    (inner, 1i32..4).prop_map(|(e, n)| Expression::Pow(Box::new(e), n as f64)),
+                ]
+            })
+        }
+
+        /// Each derivative is replaced by a single mesh value, which is enough to check that the structure
+        /// of the expression is preserved.
+        fn derivatives() -> HashMap<Vec<Variable>, MeshExpr> {
+            HashMap::from([
+                (vec![Variable::X], MeshExpr::AtOffset(vec![1, 0])),
+                (
+                    vec![Variable::Y, Variable::Y],
+                    MeshExpr::AtOffset(vec![0, 2]),
+                ),
+                (
+                    vec![Variable::X, Variable::Y],
+                    MeshExpr::AtOffset(vec![1, 1]),
+                ),
+            ])
+        }
+
+        fn symbol_value(name: &str) -> f64 {
+            match name {
+                "a" => 1.5,
+                "b" => -0.5,
+                _ => 2.25,
+            }
+        }
+
+        fn expression_leaf(e: &Expression) -> f64 {
+            match e {
+                Expression::SolutionVal => 0.8,
+                Expression::Derivative(Variable::X, 1) => -1.2,
+                Expression::Derivative(Variable::Y, 2) => 0.3,
+                Expression::CrossDerivative(_) => 1.7,
+                Expression::SymbolicConstant(c) => symbol_value(&c.to_string()),
+                other => panic!("Unexpected leaf {other:?}"),
+            }
+        }
+
+        fn mesh_leaf(e: &MeshExpr) -> f64 {
+            match e {
+                MeshExpr::AtOffset(offset) => match offset.as_slice() {
+                    [0, 0] => 0.8,
+                    [1, 0] => -1.2,
+                    [0, 2] => 0.3,
+                    [1, 1] => 1.7,
+                    other => panic!("Unexpected offset {other:?}"),
+                },
+                MeshExpr::SymbolicConst(c) | MeshExpr::FunctionVal(c) => {
+                    symbol_value(&c.to_string())
+                }
+                other => panic!("Unexpected leaf {other:?}"),
+            }
+        }
+
+        fn assert_close(a: f64, b: f64) {
+            if a.is_finite() && b.is_finite() && a.abs() < 1e6 {
+                assert!(
+                    (a - b).abs() <= 1e-9 * (1. + a.abs()),
+                    "{a} and {b} aren't equal"
+                );
+            }
+        }
+
+        proptest! {
+            #[test]
+            fn mesh_expr_matches_expression(expr in expression()) {
+                let expected = expr.evaluate(&expression_leaf);
+
+                let fns = ["f".to_string()];
+                let mesh_expr = MeshExpr::from_diff_eq(expr, &fns, &derivatives(), 2).unwrap();
+
+                assert_close(expected, mesh_expr.evaluate(&mesh_leaf));
+            }
+
+            #[test]
+            fn simplifying_preserves_value(expr in expression()) {
+                let mesh_expr = MeshExpr::from_diff_eq(expr, &[], &derivatives(), 2).unwrap();
+                let expected = mesh_expr.evaluate(&mesh_leaf);
+
+                assert_close(expected, mesh_expr.simplify().evaluate(&mesh_leaf));
+            }
+
+            #[test]
+            fn hoisting_preserves_value(expr in expression()) {
+                let mesh_expr = MeshExpr::from_diff_eq(expr, &[], &derivatives(), 2).unwrap();
+                let expected = mesh_expr.evaluate(&mesh_leaf);
+
+                let mut hoisted = Vec::new();
+                let collected = mesh_expr.collect_linear().hoist_constants(&mut hoisted);
+                let leaf = |e: &MeshExpr| match e {
+                    MeshExpr::ScaleConst(k) => hoisted[*k].evaluate(&mesh_leaf),
+                    other => mesh_leaf(other),
+                };
+
+                assert_close(expected, collected.evaluate(&leaf));
+            }
+        }
+    }
 }
@@ -0,0 +1,296 @@
+/// A system of linear equations `A x = b` where `A` is a banded matrix, meaning that `A[row][col]` can only be
+/// nonzero if `row - lower <= col <= row + upper`. These come up when an implicit scheme couples the unknowns
+/// along a row of the mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandedSystem {
+    size: usize,
+    lower: usize,
+    upper: usize,
+    /// Entries within the band, stored by row. Each row has `lower + upper + 1` entries.
+    bands: Vec<f64>,
+    rhs: Vec<f64>,
+}
+
+impl BandedSystem {
+    pub fn new(size: usize, lower: usize, upper: usize) -> Self {
+        let width = lower + upper + 1;
+
+        Self {
+            size,
+            lower,
+            upper,
+            bands: [0f64].repeat(size * width),
+            rhs: [0f64].repeat(size),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Sets an entry of the matrix.
+    ///
+    /// # Panics
+    /// If the entry is outside the band.
+    pub fn set(&mut self, row: usize, col: usize, value: f64) {
+        let idx = self.get_index(row, col);
+        self.bands[idx] = value;
+    }
+
+    pub fn get_at(&self, row: usize, col: usize) -> f64 {
+        if col + self.lower < row || col > row + self.upper {
+            return 0.;
+        }
+        self.bands[self.get_index(row, col)]
+    }
+
+    pub fn set_rhs(&mut self, row: usize, value: f64) {
+        self.rhs[row] = value;
+    }
+
+    /// Solves the system using Gaussian elimination restricted to the band, so the cost is linear in the
+    /// size of the system. For a tridiagonal system, this is the Thomas algorithm.
+    ///
+    /// No pivoting is done, so the solver assumes that the matrix is diagonally dominant, which is usually
+    /// the case for implicit finite difference schemes. Rows that aren't, such as those from some Neumann or
+    /// Robin conditions, can leave a zero on the diagonal during the elimination.
+    ///
+    /// # Panics
+    /// If a pivot is zero or negligible compared to the rest of its row, rather than filling the solution
+    /// with `inf` or `NaN`.
+    pub fn solve(mut self) -> Vec<f64> {
+        let n = self.size;
+
+        for k in 0..n {
+            let pivot = self.get_at(k, k);
+            let last_col = (k + self.upper).min(n - 1);
+
+            let scale = (k..=last_col)
+                .map(|col| self.get_at(k, col).abs())
+                .fold(0., f64::max);
+            assert!(
+                pivot.is_finite() && pivot.abs() > f64::EPSILON * scale,
+                "The banded system has a zero pivot in row {k}. The solver doesn't pivot, so the matrix \
+                 should be diagonally dominant."
+            );
+
+            for row in k + 1..=(k + self.lower).min(n - 1) {
+                let factor = self.get_at(row, k) / pivot;
+                if factor == 0. {
+                    continue;
+                }
+
+                for col in k..=last_col {
+                    let idx = self.get_index(row, col);
+                    self.bands[idx] -= factor * self.get_at(k, col);
+                }
+                self.rhs[row] -= factor * self.rhs[k];
+            }
+        }
+
+        let mut solution = [0f64].repeat(n);
+        for k in (0..n).rev() {
+            let last_col = (k + self.upper).min(n - 1);
+
+            let known: f64 = (k + 1..=last_col)
+                .map(|col| self.get_at(k, col) * solution[col])
+                .sum();
+
+            solution[k] = (self.rhs[k] - known) / self.get_at(k, k);
+        }
+
+        solution
+    }
+
+    fn get_index(&self, row: usize, col: usize) -> usize {
+        assert!(
+            col + self.lower >= row && col <= row + self.upper,
+            "Entry ({row}, {col}) is outside the band."
+        );
+        row * (self.lower + self.upper + 1) + (col + self.lower - row)
+    }
+}
+
+/// A banded system whose band wraps around the corners of the matrix, as when the unknowns lie along a periodic
+/// index, so that the first and last unknowns are neighbours. Entries that wrap around are a low rank
+/// correction to the banded part, so the system is solved with the Sherman-Morrison-Woodbury formula, which
+/// needs one banded solve for each of these entries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CyclicBandedSystem {
+    band: BandedSystem,
+    /// Entries outside the band, as `(row, col, value)`.
+    corners: Vec<(usize, usize, f64)>,
+}
+
+impl CyclicBandedSystem {
+    pub fn new(size: usize, lower: usize, upper: usize) -> Self {
+        Self {
+            band: BandedSystem::new(size, lower, upper),
+            corners: Vec::new(),
+        }
+    }
+
+    /// Adds to an entry of the matrix, where the column is taken modulo the size of the system. Entries are
+    /// added rather than set, as on a short system two offsets can wrap around to the same column.
+    pub fn add(&mut self, row: usize, col: isize, value: f64) {
+        let col = col.rem_euclid(self.band.size as isize) as usize;
+
+        if col + self.band.lower >= row && col <= row + self.band.upper {
+            let idx = self.band.get_index(row, col);
+            self.band.bands[idx] += value;
+        } else {
+            match self
+                .corners
+                .iter_mut()
+                .find(|(r, c, _)| (*r, *c) == (row, col))
+            {
+                Some((_, _, v)) => *v += value,
+                None => self.corners.push((row, col, value)),
+            }
+        }
+    }
+
+    pub fn set_rhs(&mut self, row: usize, value: f64) {
+        self.band.set_rhs(row, value);
+    }
+
+    /// Solves the system. Writing the matrix as `B + U V^T`, where `B` is the band and each column of `U` and
+    /// `V` holds one corner entry, the solution is `y - Z w`, with `B y = b`, `B Z = U` and
+    /// `(I + V^T Z) w = V^T y`.
+    ///
+    /// # Panics
+    /// As [`BandedSystem::solve`], if the band has a zero pivot.
+    pub fn solve(self) -> Vec<f64> {
+        let n = self.band.size;
+        let m = self.corners.len();
+
+        let solve_band = |rhs: Vec<f64>| {
+            let mut system = self.band.clone();
+            system.rhs = rhs;
+            system.solve()
+        };
+
+        let y = solve_band(self.band.rhs.clone());
+        if m == 0 {
+            return y;
+        }
+
+        let z: Vec<Vec<f64>> = self
+            .corners
+            .iter()
+            .map(|&(row, _, value)| {
+                let mut column = [0f64].repeat(n);
+                column[row] = value;
+                solve_band(column)
+            })
+            .collect();
+
+        // The small system for the weights of the corrections, which is dense
+        let mut capacitance = BandedSystem::new(m, m - 1, m - 1);
+        for (k, &(_, col, _)) in self.corners.iter().enumerate() {
+            for (l, z_l) in z.iter().enumerate() {
+                let identity = if k == l { 1. } else { 0. };
+                capacitance.set(k, l, identity + z_l[col]);
+            }
+            capacitance.set_rhs(k, y[col]);
+        }
+        let w = capacitance.solve();
+
+        (0..n)
+            .map(|i| y[i] - z.iter().zip(&w).map(|(z_l, w_l)| z_l[i] * w_l).sum::<f64>())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BandedSystem, CyclicBandedSystem};
+
+    #[test]
+    fn tridiagonal() {
+        // 2x0 - x1 = 1, -x0 + 2x1 - x2 = 0, -x1 + 2x2 = 1 has the solution x = [1, 1, 1]
+        let mut system = BandedSystem::new(3, 1, 1);
+        for k in 0..3 {
+            system.set(k, k, 2.);
+            if k > 0 {
+                system.set(k, k - 1, -1.);
+            }
+            if k < 2 {
+                system.set(k, k + 1, -1.);
+            }
+        }
+        system.set_rhs(0, 1.);
+        system.set_rhs(2, 1.);
+
+        let solution = system.solve();
+
+        for x in solution {
+            assert!((x - 1.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "zero pivot in row 1")]
+    fn zero_pivot() {
+        // x0 + x1 = 1, x0 + x1 = 2 is singular, which shows up after eliminating the first row
+        let mut system = BandedSystem::new(2, 1, 1);
+        for row in 0..2 {
+            system.set(row, 0, 1.);
+            system.set(row, 1, 1.);
+            system.set_rhs(row, (row + 1) as f64);
+        }
+
+        system.solve();
+    }
+
+    #[test]
+    fn asymmetric_band() {
+        // Lower bandwidth 2 and upper bandwidth 0, so the system is solved by forward substitution
+        let mut system = BandedSystem::new(4, 2, 0);
+        let expected = [1., -2., 0.5, 3.];
+
+        for row in 0..4usize {
+            let band = row.saturating_sub(2)..=row;
+            let mut rhs = 0.;
+            for (col, x) in expected
+                .iter()
+                .enumerate()
+                .filter(|(col, _)| band.contains(col))
+            {
+                let value = (row + 2 * col + 1) as f64;
+                system.set(row, col, value);
+                rhs += value * x;
+            }
+            system.set_rhs(row, rhs);
+        }
+
+        let solution = system.solve();
+
+        for (x, e) in solution.iter().zip(expected) {
+            assert!((x - e).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn cyclic_tridiagonal() {
+        // A periodic second difference with a diagonal shift: 3x_i - x_(i-1) - x_(i+1) = b_i
+        let expected = [1., -2., 0.5, 3., 0.];
+        let n = expected.len();
+
+        let mut system = CyclicBandedSystem::new(n, 1, 1);
+        for i in 0..n {
+            system.add(i, i as isize - 1, -1.);
+            system.add(i, i as isize, 3.);
+            system.add(i, i as isize + 1, -1.);
+
+            let rhs = 3. * expected[i] - expected[(i + n - 1) % n] - expected[(i + 1) % n];
+            system.set_rhs(i, rhs);
+        }
+
+        let solution = system.solve();
+
+        for (x, e) in solution.iter().zip(expected) {
+            assert!((x - e).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{iterative::ConvergenceReport, mesh2d::PhysicalCoordinate};
+
+/// How the nodes along a line are distributed, given as parameters from 0 to 1 at each node. Stretched
+/// distributions cluster nodes where the solution changes quickly, such as near walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stretching {
+    /// Equally spaced nodes.
+    Uniform,
+    /// Nodes clustered towards both ends with a hyperbolic tangent. Larger factors cluster the nodes more
+    /// tightly, and the distribution approaches the uniform one as the factor goes to zero.
+    Tanh(f64),
+    /// Each spacing is the previous one multiplied by the ratio, so a ratio above 1 clusters the nodes
+    /// towards the start of the line, and a ratio below 1 towards the end.
+    Geometric(f64),
+}
+
+impl Stretching {
+    /// The parameters of `num_points` nodes, starting at 0 and ending at 1.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 nodes.
+    pub fn distribute(&self, num_points: usize) -> Vec<f64> {
+        assert!(num_points >= 2, "A line needs at least 2 nodes.");
+
+        let last = (num_points - 1) as f64;
+        (0..num_points)
+            .map(|k| {
+                let s = k as f64 / last;
+                match *self {
+                    Self::Tanh(factor) if factor != 0. => {
+                        0.5 * (1. + (factor * (s - 0.5)).tanh() / (0.5 * factor).tanh())
+                    }
+                    Self::Geometric(ratio) if ratio != 1. => {
+                        (ratio.powi(k as i32) - 1.) / (ratio.powf(last) - 1.)
+                    }
+                    _ => s,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The elliptic system that [`StructuredGrid::smooth`] solves for the positions of the interior nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EllipticSystem {
+    /// Winslow's equations, where the computational coordinates are harmonic functions of the physical
+    /// ones. This gives smooth grids whose lines don't cross, but spreads out any clustering of the nodes.
+    Winslow,
+    /// The Thompson-Thames-Mastin equations, which add control functions to Winslow's equations. These are
+    /// found from the spacing of the nodes on the boundaries (as suggested by Thomas and Middlecoff), so the
+    /// clustering of the boundary nodes carries into the interior.
+    Thompson,
+}
+
+/// The physical positions of the nodes of a structured grid, which can be turned into a curvilinear mesh
+/// with [`crate::mesh2d::FiniteDiffMesh::from_grid`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructuredGrid {
+    points: Vec<PhysicalCoordinate>,
+    width: usize,
+}
+
+impl StructuredGrid {
+    /// Builds a grid from the four curves bounding a region by transfinite interpolation, where each node is
+    /// a blend of the boundary points in line with it. `s` and `t` are the parameters of the nodes along the
+    /// first and second index (see [`Stretching::distribute`]).
+    ///
+    /// Each curve is parametrised from 0 to 1: `bottom` and `top` run along the first index, and `left` and
+    /// `right` along the second. They should meet at the corners, so `bottom(0) = left(0)`,
+    /// `bottom(1) = right(0)`, `top(0) = left(1)` and `top(1) = right(1)`.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 nodes in either direction.
+    pub fn transfinite<B, T, L, R>(
+        bottom: B,
+        top: T,
+        left: L,
+        right: R,
+        s: &[f64],
+        t: &[f64],
+    ) -> Self
+    where
+        B: Fn(f64) -> PhysicalCoordinate,
+        T: Fn(f64) -> PhysicalCoordinate,
+        L: Fn(f64) -> PhysicalCoordinate,
+        R: Fn(f64) -> PhysicalCoordinate,
+    {
+        assert!(
+            s.len() >= 2 && t.len() >= 2,
+            "A grid needs at least 2 nodes in each direction."
+        );
+
+        let corners = [bottom(0.), bottom(1.), top(0.), top(1.)];
+
+        let mut points = Vec::with_capacity(s.len() * t.len());
+        for &t in t {
+            let (l, r) = (left(t), right(t));
+
+            for &s in s {
+                let (b, tp) = (bottom(s), top(s));
+
+                // Blend of the curves on either side in each direction, less the bilinear interpolation of
+                // the corners, which both blends include
+                let blend = |get: fn(&PhysicalCoordinate) -> f64| {
+                    (1. - t) * get(&b) + t * get(&tp) + (1. - s) * get(&l) + s * get(&r)
+                        - (1. - s) * (1. - t) * get(&corners[0])
+                        - s * (1. - t) * get(&corners[1])
+                        - (1. - s) * t * get(&corners[2])
+                        - s * t * get(&corners[3])
+                };
+
+                points.push(PhysicalCoordinate::new(blend(|p| p.x), blend(|p| p.y)));
+            }
+        }
+
+        Self {
+            points,
+            width: s.len(),
+        }
+    }
+
+    /// Moves the interior nodes to solve an elliptic system, with the boundary nodes fixed. This removes
+    /// kinks that transfinite interpolation carries in from the boundaries, and keeps grid lines from
+    /// crossing in concave regions. The system is solved by Gauss-Seidel sweeps until the largest
+    /// movement of a node in a sweep is below `tolerance`, or `max_iters` sweeps have been done.
+    pub fn smooth(
+        &mut self,
+        system: EllipticSystem,
+        tolerance: f64,
+        max_iters: usize,
+    ) -> ConvergenceReport {
+        let (width, height) = (self.width, self.height());
+        let (phi, psi) = match system {
+            EllipticSystem::Winslow => (vec![0.; 2 * width], vec![0.; 2 * height]),
+            EllipticSystem::Thompson => self.control_functions(),
+        };
+
+        let mut residuals = Vec::new();
+        for _ in 0..max_iters {
+            let mut max_movement = 0f64;
+
+            for j in 1..height.saturating_sub(1) {
+                // The control functions are interpolated between the boundaries they are found on
+                let t = j as f64 / (height - 1) as f64;
+
+                for i in 1..width - 1 {
+                    let u = i as f64 / (width - 1) as f64;
+                    let phi = (1. - t) * phi[i] + t * phi[width + i];
+                    let psi = (1. - u) * psi[j] + u * psi[height + j];
+
+                    let at = |di: isize, dj: isize| {
+                        self.get((i as isize + di) as usize, (j as isize + dj) as usize)
+                    };
+                    let (east, west, north, south) = (at(1, 0), at(-1, 0), at(0, 1), at(0, -1));
+                    let cross = |get: fn(&PhysicalCoordinate) -> f64| {
+                        (get(&at(1, 1)) - get(&at(1, -1)) - get(&at(-1, 1)) + get(&at(-1, -1))) / 4.
+                    };
+
+                    let (x_p, y_p) = ((east.x - west.x) / 2., (east.y - west.y) / 2.);
+                    let (x_q, y_q) = ((north.x - south.x) / 2., (north.y - south.y) / 2.);
+
+                    let alpha = x_q * x_q + y_q * y_q;
+                    let beta = x_p * x_q + y_p * y_q;
+                    let gamma = x_p * x_p + y_p * y_p;
+
+                    // alpha (r_pp + phi r_p) - 2 beta r_pq + gamma (r_qq + psi r_q) = 0, solved for the
+                    // position of the node
+                    let solve = |get: fn(&PhysicalCoordinate) -> f64, r_p: f64, r_q: f64| {
+                        (alpha * (get(&east) + get(&west) + phi * r_p)
+                            + gamma * (get(&north) + get(&south) + psi * r_q)
+                            - 2. * beta * cross(get))
+                            / (2. * (alpha + gamma))
+                    };
+                    let new =
+                        PhysicalCoordinate::new(solve(|p| p.x, x_p, x_q), solve(|p| p.y, y_p, y_q));
+
+                    let old = self.get(i, j);
+                    max_movement = max_movement.max((new.x - old.x).hypot(new.y - old.y));
+                    self.points[i + j * width] = new;
+                }
+            }
+
+            residuals.push(max_movement);
+            if max_movement < tolerance {
+                break;
+            }
+        }
+
+        ConvergenceReport::new(residuals, tolerance)
+    }
+
+    /// The control functions of the Thompson system, from the spacing of the nodes on the boundaries. The
+    /// first holds `phi` on the bottom then the top boundary, and the second `psi` on the left then the
+    /// right boundary. `phi` is chosen so that `r_pp + phi r_p` has no component along the boundary, which
+    /// the boundary nodes then satisfy, and likewise for `psi`.
+    fn control_functions(&self) -> (Vec<f64>, Vec<f64>) {
+        let (width, height) = (self.width, self.height());
+
+        let control = |line: &dyn Fn(usize) -> PhysicalCoordinate, len: usize| -> Vec<f64> {
+            (0..len)
+                .map(|k| {
+                    if k == 0 || k == len - 1 {
+                        return 0.;
+                    }
+
+                    let (prev, here, next) = (line(k - 1), line(k), line(k + 1));
+                    let (x_s, y_s) = ((next.x - prev.x) / 2., (next.y - prev.y) / 2.);
+                    let x_ss = next.x - 2. * here.x + prev.x;
+                    let y_ss = next.y - 2. * here.y + prev.y;
+
+                    -(x_s * x_ss + y_s * y_ss) / (x_s * x_s + y_s * y_s)
+                })
+                .collect()
+        };
+
+        let mut phi = control(&|i| self.get(i, 0), width);
+        phi.extend(control(&|i| self.get(i, height - 1), width));
+
+        let mut psi = control(&|j| self.get(0, j), height);
+        psi.extend(control(&|j| self.get(width - 1, j), height));
+
+        (phi, psi)
+    }
+
+    /// The position of node `(i, j)`.
+    pub fn get(&self, i: usize, j: usize) -> PhysicalCoordinate {
+        self.points[i + j * self.width]
+    }
+
+    /// The number of nodes along the first index.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of nodes along the second index.
+    pub fn height(&self) -> usize {
+        self.points.len() / self.width
+    }
+
+    /// The lines of nodes along the first index, in the layout taken by
+    /// [`crate::mesh2d::FiniteDiffMesh::from_physical_domain`].
+    pub fn lines(&self) -> Vec<&[PhysicalCoordinate]> {
+        self.points.chunks(self.width).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use super::{EllipticSystem, Stretching, StructuredGrid};
+    use crate::mesh2d::PhysicalCoordinate;
+
+    fn rectangle(s: &[f64], t: &[f64]) -> StructuredGrid {
+        StructuredGrid::transfinite(
+            |s| PhysicalCoordinate::new(2. * s, 0.),
+            |s| PhysicalCoordinate::new(2. * s, 1.),
+            |t| PhysicalCoordinate::new(0., t),
+            |t| PhysicalCoordinate::new(2., t),
+            s,
+            t,
+        )
+    }
+
+    #[test]
+    fn stretching() {
+        for stretching in [
+            Stretching::Uniform,
+            Stretching::Tanh(3.),
+            Stretching::Geometric(1.2),
+        ] {
+            let s = stretching.distribute(11);
+
+            assert_eq!(s.len(), 11);
+            assert!(s[0].abs() < 1e-12 && (s[10] - 1.).abs() < 1e-12);
+            assert!(s.windows(2).all(|w| w[1] > w[0]));
+        }
+
+        // Tanh clusters at both ends, and geometric growth at the start
+        let s = Stretching::Tanh(3.).distribute(11);
+        assert!(s[1] - s[0] < 0.1 && s[6] - s[5] > 0.1);
+        assert!((s[1] + s[9] - 1.).abs() < 1e-12);
+
+        let s = Stretching::Geometric(1.2).distribute(11);
+        assert!((s[2] - s[1]) / (s[1] - s[0]) - 1.2 < 1e-12);
+    }
+
+    #[test]
+    fn transfinite_annulus() {
+        let s = Stretching::Uniform.distribute(9);
+        let t = Stretching::Uniform.distribute(5);
+        let arc =
+            |r: f64| move |s: f64| PhysicalCoordinate::new(r * (PI * s).cos(), r * (PI * s).sin());
+
+        let grid = StructuredGrid::transfinite(
+            arc(1.),
+            arc(2.),
+            |t| PhysicalCoordinate::new(1. + t, 0.),
+            |t| PhysicalCoordinate::new(-1. - t, 0.),
+            &s,
+            &t,
+        );
+
+        assert_eq!((grid.width(), grid.height()), (9, 5));
+        assert_eq!(grid.lines().len(), 5);
+        // The boundary nodes lie on the curves
+        for i in 0..9 {
+            let p = grid.get(i, 4);
+            assert!((p.x.hypot(p.y) - 2.).abs() < 1e-12);
+        }
+        // Interior nodes are in between
+        let p = grid.get(4, 2);
+        assert!(p.x.abs() < 1e-12 && p.y > 1. && p.y < 2.);
+    }
+
+    #[test]
+    fn thompson_keeps_clustering() {
+        let s = Stretching::Tanh(2.).distribute(9);
+        let t = Stretching::Geometric(1.3).distribute(7);
+        let algebraic = rectangle(&s, &t);
+
+        // Nodes clustered by a tensor product of stretchings already satisfy the Thompson system
+        let mut grid = algebraic.clone();
+        let report = grid.smooth(EllipticSystem::Thompson, 1e-12, 100);
+        assert!(report.converged && report.iterations == 1);
+        for (p, q) in grid.points.iter().zip(&algebraic.points) {
+            assert!((p.x - q.x).hypot(p.y - q.y) < 1e-12);
+        }
+
+        // Winslow's equations spread the nodes out
+        let report = grid.smooth(EllipticSystem::Winslow, 1e-10, 1000);
+        assert!(report.converged);
+        let middle = grid.get(4, 3);
+        assert!((middle.x - 1.).abs() < 1e-8);
+        assert!((grid.get(1, 3).x - algebraic.get(1, 3).x).abs() > 1e-3);
+    }
+
+    #[test]
+    fn winslow_fixes_boundaries() {
+        let s = Stretching::Uniform.distribute(11);
+        let bump = |s: f64| PhysicalCoordinate::new(s, 0.3 * (PI * s).sin().powi(2));
+
+        let mut grid = StructuredGrid::transfinite(
+            bump,
+            |s| PhysicalCoordinate::new(s, 1.),
+            |t| PhysicalCoordinate::new(0., t),
+            |t| PhysicalCoordinate::new(1., t),
+            &s,
+            &s,
+        );
+        let before = grid.clone();
+
+        assert!(grid.smooth(EllipticSystem::Winslow, 1e-10, 1000).converged);
+        for k in 0..11 {
+            assert_eq!(grid.get(k, 0), before.get(k, 0));
+            assert_eq!(grid.get(0, k), before.get(0, k));
+        }
+        // The bump spreads into the interior more smoothly, but the nodes stay in order
+        for j in 0..10 {
+            assert!(grid.get(5, j + 1).y > grid.get(5, j).y);
+        }
+    }
+}
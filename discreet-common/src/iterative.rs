@@ -0,0 +1,77 @@
+/// A method for iteratively solving the system of equations given by a scheme, used for steady problems where
+/// the value at each node depends on its neighbours in every direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IterativeMethod {
+    /// Every node is updated using the values from the previous sweep.
+    Jacobi,
+    /// Nodes are updated in place, so later nodes in a sweep use the values already updated in that sweep.
+    GaussSeidel,
+    /// Successive over-relaxation: Gauss-Seidel, with each update scaled by the relaxation factor. Factors
+    /// between 1 and 2 usually converge faster than Gauss-Seidel.
+    Sor(f64),
+}
+
+impl IterativeMethod {
+    /// The value a node is updated to, given its current value and the value that satisfies the scheme at
+    /// that node.
+    pub fn relax(&self, old: f64, new: f64) -> f64 {
+        match self {
+            Self::Jacobi | Self::GaussSeidel => new,
+            Self::Sor(omega) => old + omega * (new - old),
+        }
+    }
+
+    /// Whether updated values are only written back after the whole sweep.
+    pub fn is_simultaneous(&self) -> bool {
+        matches!(self, Self::Jacobi)
+    }
+}
+
+/// How an iterative solve went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConvergenceReport {
+    /// Number of sweeps that were done.
+    pub iterations: usize,
+    /// The largest residual of the scheme after each sweep.
+    pub residuals: Vec<f64>,
+    /// Whether the residual dropped below the tolerance before the iteration limit was reached.
+    pub converged: bool,
+}
+
+impl ConvergenceReport {
+    pub fn new(residuals: Vec<f64>, tolerance: f64) -> Self {
+        Self {
+            iterations: residuals.len(),
+            converged: residuals.last().is_some_and(|r| *r < tolerance),
+            residuals,
+        }
+    }
+
+    /// The residual after the last sweep, or infinity if no sweeps were done.
+    pub fn final_residual(&self) -> f64 {
+        self.residuals.last().copied().unwrap_or(f64::INFINITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConvergenceReport, IterativeMethod};
+
+    #[test]
+    fn relaxation() {
+        assert_eq!(IterativeMethod::Jacobi.relax(1., 3.), 3.);
+        assert_eq!(IterativeMethod::GaussSeidel.relax(1., 3.), 3.);
+        assert_eq!(IterativeMethod::Sor(1.5).relax(1., 3.), 4.);
+    }
+
+    #[test]
+    fn convergence_report() {
+        let report = ConvergenceReport::new(vec![1., 0.1, 0.001], 0.01);
+        assert_eq!(report.iterations, 3);
+        assert!(report.converged);
+        assert_eq!(report.final_residual(), 0.001);
+
+        let report = ConvergenceReport::new(vec![], 0.01);
+        assert!(!report.converged);
+    }
+}
@@ -1,3 +1,9 @@
 pub mod algebra;
+pub mod banded;
+pub mod grid;
+pub mod iterative;
+pub mod mesh1d;
 pub mod mesh2d;
+pub mod mesh3d;
 pub mod taylor;
+pub mod timestepping;
@@ -0,0 +1,295 @@
+use std::ops::Range;
+
+use crate::{algebra::Variable, mesh2d::BoundaryCondition, taylor::nonuniform_weights};
+
+/// A uniform mesh with a single index, for ODEs, two-point boundary value problems, and prototyping schemes
+/// before moving to 2D.
+pub struct FiniteDiffMesh1D {
+    solution_vals: Vec<f64>,
+    /// The coordinate of the first node, and the spacing of the nodes.
+    min: f64,
+    spacing: f64,
+
+    /// Conditions that are reapplied to the ends of the mesh, in the order they were set.
+    boundary_conditions: Vec<(Boundary, StoredCondition)>,
+}
+
+impl FiniteDiffMesh1D {
+    /// A mesh with `num` nodes evenly spaced from `xmin` to `xmax`.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 nodes.
+    pub fn from_num_points(xmin: f64, xmax: f64, num: usize) -> Self {
+        assert!(num >= 2, "A mesh needs at least 2 nodes.");
+
+        Self {
+            solution_vals: [0f64].repeat(num),
+            min: xmin,
+            spacing: (xmax - xmin) / ((num - 1) as f64),
+            boundary_conditions: Vec::new(),
+        }
+    }
+
+    pub fn get_at(&self, i: usize) -> f64 {
+        self.solution_vals[i]
+    }
+
+    pub fn set_at(&mut self, i: usize, value: f64) {
+        self.solution_vals[i] = value;
+    }
+
+    /// The value at an offset from node `i`.
+    pub fn get_at_offset(&self, i: usize, di: isize) -> f64 {
+        self.get_at(self.offset_index(i, di))
+    }
+
+    /// Sets the value at an offset from node `i`.
+    pub fn set_at_offset(&mut self, i: usize, di: isize, value: f64) {
+        self.set_at(self.offset_index(i, di), value);
+    }
+
+    /// The index in the values of the mesh of the node at an offset from node `i`.
+    pub fn offset_index(&self, i: usize, di: isize) -> usize {
+        (i as isize + di) as usize
+    }
+
+    /// The nodes that a stencil reaching from `min_offset` to `max_offset` can be centered on.
+    ///
+    /// # Panics
+    /// If `variable` isn't [`Variable::X`], as the mesh only has one index.
+    pub fn centers(
+        &self,
+        variable: Variable,
+        min_offset: isize,
+        max_offset: isize,
+    ) -> Range<usize> {
+        assert_eq!(variable, Variable::X, "1D meshes only have an X index");
+        (-min_offset) as usize..self.len().saturating_sub(max_offset as usize)
+    }
+
+    /// Sets the condition on an end of the mesh, replacing any condition that was previously set on it.
+    /// Conditions are applied by the generated scheme after each iteration using
+    /// [`Self::apply_boundary_conditions`].
+    pub fn set_boundary_condition(&mut self, bound: Boundary, condition: BoundaryCondition) {
+        self.boundary_conditions.retain(|(b, _)| *b != bound);
+        self.boundary_conditions
+            .push((bound, StoredCondition::Derivative(condition)));
+    }
+
+    /// Sets the value at an end of the mesh to a function of its coordinate, replacing any condition that was
+    /// previously set on it. The function is kept, and is evaluated again whenever the boundary conditions
+    /// are applied.
+    pub fn set_dirichlet<F: Fn(f64) -> f64 + 'static>(&mut self, bound: Boundary, func: F) {
+        self.boundary_conditions.retain(|(b, _)| *b != bound);
+        self.boundary_conditions
+            .push((bound, StoredCondition::Dirichlet(Box::new(func))));
+
+        self.apply_condition(bound);
+    }
+
+    /// Updates both ends of the mesh if they have a condition set on them, using the current values next to
+    /// them.
+    pub fn apply_boundary_conditions(&mut self) {
+        for k in 0..self.boundary_conditions.len() {
+            self.apply_condition(self.boundary_conditions[k].0);
+        }
+    }
+
+    /// The condition set on an end of the mesh as a linear equation `sum(coeff * u(i)) = rhs` in the values
+    /// of the nodes going into the domain from the end. The normal derivative uses a second order one-sided
+    /// difference. Gives `None` if no condition is set.
+    ///
+    /// This allows implicit schemes to solve for the ends along with the rest of the mesh.
+    pub fn boundary_equation(&self, bound: Boundary) -> Option<BoundaryEquation> {
+        let (_, condition) = self.boundary_conditions.iter().find(|(b, _)| *b == bound)?;
+
+        // Nodes going into the domain, starting at the boundary
+        let len = self.len();
+        let node = |k: usize| match bound {
+            Boundary::Left => k,
+            Boundary::Right => len - 1 - k,
+        };
+
+        let (a, b, g) = match condition {
+            StoredCondition::Dirichlet(func) => {
+                return Some((vec![(node(0), 1.)], func(self.coordinate(node(0)))));
+            }
+            StoredCondition::Derivative(BoundaryCondition::Neumann(g)) => (0., 1., *g),
+            StoredCondition::Derivative(BoundaryCondition::Robin { a, b, g }) => (*a, *b, *g),
+        };
+
+        // The outward normal points away from the nodes going into the domain, so du/dn = -du/ds
+        let num_nodes = len.min(3);
+        let distances: Vec<f64> = (0..num_nodes).map(|k| k as f64 * self.spacing).collect();
+        let weights = nonuniform_weights(&distances, 1)
+            .expect("Distinct nodes approximate the first derivative");
+
+        let mut terms = vec![(node(0), a)];
+        for (k, weight) in weights.into_iter().enumerate() {
+            match terms.iter_mut().find(|(n, _)| *n == node(k)) {
+                Some((_, c)) => *c -= b * weight,
+                None => terms.push((node(k), -b * weight)),
+            }
+        }
+
+        Some((terms, g))
+    }
+
+    /// How far from an end the nodes of the boundary equations reach, which is 2 if a condition on the normal
+    /// derivative is set on either end, and 0 otherwise. Implicit schemes need their band to be at least this
+    /// wide.
+    pub fn boundary_reach(&self) -> usize {
+        let has_derivative = self
+            .boundary_conditions
+            .iter()
+            .any(|(_, c)| matches!(c, StoredCondition::Derivative(_)));
+
+        if has_derivative {
+            2.min(self.len() - 1)
+        } else {
+            0
+        }
+    }
+
+    /// Sets the value of the node at an end of the mesh so that it satisfies the condition set on it.
+    fn apply_condition(&mut self, bound: Boundary) {
+        let Some((terms, rhs)) = self.boundary_equation(bound) else {
+            return;
+        };
+
+        let (node, diagonal) = terms[0];
+        let known: f64 = terms[1..]
+            .iter()
+            .map(|&(i, coeff)| coeff * self.get_at(i))
+            .sum();
+
+        self.set_at(node, (rhs - known) / diagonal);
+    }
+
+    /// The physical coordinate of node `i`.
+    pub fn coordinate(&self, i: usize) -> f64 {
+        self.min + i as f64 * self.spacing
+    }
+
+    /// Evaluates a function of the physical coordinate at every node, giving the values in the same order as
+    /// the nodes are stored.
+    pub fn evaluate<F: Fn(f64) -> f64>(&self, func: F) -> Vec<f64> {
+        (0..self.len()).map(|i| func(self.coordinate(i))).collect()
+    }
+
+    /// The values at every node.
+    pub fn values(&self) -> &[f64] {
+        &self.solution_vals
+    }
+
+    /// Replaces the values at every node.
+    ///
+    /// # Panics
+    /// If the number of values isn't the number of nodes.
+    pub fn set_values(&mut self, values: &[f64]) {
+        self.solution_vals.copy_from_slice(values);
+    }
+
+    pub fn index_iter(&self) -> Range<usize> {
+        0..self.len()
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.solution_vals.len()
+    }
+
+    /// Whether the mesh has no nodes, which is never the case for a mesh built with [`Self::from_num_points`].
+    pub fn is_empty(&self) -> bool {
+        self.solution_vals.is_empty()
+    }
+
+    /// The spacing of the nodes, `dx`.
+    pub fn spacing(&self) -> f64 {
+        self.spacing
+    }
+
+    pub fn save_values(&self, file: &str) {
+        let bytes: Vec<u8> = self
+            .solution_vals
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        std::fs::write(file, bytes).expect("Writing failed");
+    }
+
+    /// The index of node `i` in the values of the mesh, which is `i` itself. This matches the other meshes,
+    /// so that values stored by node can be indexed the same way.
+    pub fn get_index(&self, i: usize) -> usize {
+        i
+    }
+}
+
+/// Identifies an end of a 1D mesh. Left is where the index is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    Left,
+    Right,
+}
+
+/// A condition kept by the mesh to be reapplied to an end.
+enum StoredCondition {
+    Derivative(BoundaryCondition),
+    Dirichlet(Box<dyn Fn(f64) -> f64>),
+}
+
+/// A linear equation `sum(coeff * u(i)) = rhs` in the values of some nodes, given as the list of nodes with
+/// their coefficients, and the right hand side.
+pub type BoundaryEquation = (Vec<(usize, f64)>, f64);
+
+#[cfg(test)]
+mod test {
+    use super::{Boundary, FiniteDiffMesh1D};
+    use crate::mesh2d::BoundaryCondition;
+
+    /// A mesh over `[0, 2]` with `u = x^2`, for which the one-sided differences are exact.
+    fn quadratic_mesh() -> FiniteDiffMesh1D {
+        let mut mesh = FiniteDiffMesh1D::from_num_points(0., 2., 11);
+        for (i, value) in mesh.evaluate(|x| x * x).into_iter().enumerate() {
+            mesh.set_at(i, value);
+        }
+
+        mesh
+    }
+
+    #[test]
+    fn derivative_conditions() {
+        let mut mesh = quadratic_mesh();
+
+        mesh.set_at(0, 100.);
+        mesh.set_at(10, 100.);
+        // Outward normals point in -x on the left and +x on the right
+        mesh.set_boundary_condition(Boundary::Left, BoundaryCondition::Neumann(0.));
+        mesh.set_boundary_condition(
+            Boundary::Right,
+            BoundaryCondition::Robin {
+                a: 1.,
+                b: 2.,
+                g: 12.,
+            },
+        );
+        mesh.apply_boundary_conditions();
+
+        assert!(mesh.get_at(0).abs() < 1e-12);
+        assert!((mesh.get_at(10) - 4.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dirichlet() {
+        let mut mesh = quadratic_mesh();
+
+        mesh.set_dirichlet(Boundary::Right, |x| 3. * x);
+        assert_eq!(mesh.get_at(10), 6.);
+
+        mesh.set_at(10, 0.);
+        mesh.apply_boundary_conditions();
+        assert_eq!(mesh.get_at(10), 6.);
+        assert_eq!(mesh.get_at_offset(10, -5), 1.);
+        assert!(mesh.boundary_equation(Boundary::Left).is_none());
+    }
+}
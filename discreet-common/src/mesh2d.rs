@@ -1,3 +1,11 @@
+use std::ops::Range;
+
+use crate::{
+    algebra::Variable,
+    grid::StructuredGrid,
+    taylor::{TaylorTable, nonuniform_weights},
+};
+
 /// Represents a mesh in the computational domain for a finite difference method.
 /// This mesh contains a grid of values that is used for performing the computations.
 pub struct FiniteDiffMesh {
@@ -8,12 +16,145 @@ pub struct FiniteDiffMesh {
     /// Width of the grid. This is used to allow attributes of nodes to be stored in 1D,
     /// with indexing in 2D being handled by converting using this width.
     width: usize,
+
+    /// Conditions that are reapplied to the boundary nodes after each iteration, in the order they were set.
+    boundary_conditions: Vec<(Boundary, StoredCondition)>,
+
+    /// Whether the mesh wraps around along the first and second index respectively.
+    periodic: [bool; 2],
+
+    /// Second derivatives of the computational coordinates at each node of a curvilinear mesh, in the order
+    /// given by [`Self::get_metric_derivatives`]. Empty for simple grids.
+    metric_derivatives: Vec<[f64; 6]>,
 }
 
 impl FiniteDiffMesh {
-    /// Transforms the physical domain into computational domain to allow
-    pub fn from_physical_domain(_points: &[&[PhysicalCoordinate]]) -> Self {
-        todo!()
+    /// Transforms the physical domain into computational domain to allow schemes on a structured curvilinear
+    /// mesh to be computed on a uniform grid. `points[j][i]` is the physical position of node `(i, j)`, so
+    /// each slice is a line of nodes along the first index.
+    ///
+    /// The computational coordinates `(p, q)` are the indices of the nodes, so they are spaced by 1. The
+    /// metrics `dp/dx, dp/dy, dq/dx, dq/dy` are found at each node by inverting the Jacobian of the
+    /// transformation, whose entries are approximated with second order finite differences.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 nodes in either direction, the lines have different numbers of nodes, or
+    /// the transformation is singular at a node (e.g. two nodes are at the same point).
+    pub fn from_physical_domain(points: &[&[PhysicalCoordinate]]) -> Self {
+        let height = points.len();
+        let width = points.first().map_or(0, |line| line.len());
+
+        assert!(
+            width >= 2 && height >= 2,
+            "A mesh needs at least 2 nodes in each direction."
+        );
+        assert!(
+            points.iter().all(|line| line.len() == width),
+            "Every line of the mesh should have the same number of nodes."
+        );
+
+        let points: Vec<PhysicalCoordinate> = points
+            .iter()
+            .flat_map(|line| line.iter().copied())
+            .collect();
+
+        let mut metrics = Vec::with_capacity(points.len());
+        for j in 0..height {
+            for i in 0..width {
+                let x_p = first_derivative(|k| points[k + j * width].x, i, width);
+                let y_p = first_derivative(|k| points[k + j * width].y, i, width);
+                let x_q = first_derivative(|k| points[i + k * width].x, j, height);
+                let y_q = first_derivative(|k| points[i + k * width].y, j, height);
+
+                let jacobian = x_p * y_q - x_q * y_p;
+                assert!(
+                    jacobian.abs() > 1e-12,
+                    "The transformation is singular at node ({i}, {j})."
+                );
+
+                metrics.push((
+                    y_q / jacobian,
+                    -x_q / jacobian,
+                    -y_p / jacobian,
+                    x_p / jacobian,
+                ));
+            }
+        }
+
+        // Second derivatives, from differentiating the metrics themselves with the chain rule,
+        // e.g. p_xx = (p_x)_p p_x + (p_x)_q q_x
+        let mut metric_derivatives = Vec::with_capacity(points.len());
+        for j in 0..height {
+            for i in 0..width {
+                let (p_x, p_y, q_x, q_y) = metrics[i + j * width];
+
+                let d_dx_d_dy = |component: fn(&(f64, f64, f64, f64)) -> f64| {
+                    let along_p =
+                        first_derivative(|k| component(&metrics[k + j * width]), i, width);
+                    let along_q =
+                        first_derivative(|k| component(&metrics[i + k * width]), j, height);
+                    (along_p * p_x + along_q * q_x, along_p * p_y + along_q * q_y)
+                };
+
+                let (p_xx, p_xy) = d_dx_d_dy(|m| m.0);
+                let (_, p_yy) = d_dx_d_dy(|m| m.1);
+                let (q_xx, q_xy) = d_dx_d_dy(|m| m.2);
+                let (_, q_yy) = d_dx_d_dy(|m| m.3);
+
+                metric_derivatives.push([p_xx, p_xy, p_yy, q_xx, q_xy, q_yy]);
+            }
+        }
+
+        Self {
+            solution_vals: [0f64].repeat(points.len()),
+            scalings: MeshScaling::ComplexPhysDomain(metrics),
+            points,
+            width,
+            boundary_conditions: Vec::new(),
+            periodic: [false; 2],
+            metric_derivatives,
+        }
+    }
+
+    /// A curvilinear mesh with the nodes of a generated grid, e.g. one from transfinite interpolation of the
+    /// curves bounding a region. See [`Self::from_physical_domain`].
+    pub fn from_grid(grid: &StructuredGrid) -> Self {
+        Self::from_physical_domain(&grid.lines())
+    }
+
+    /// A rectilinear mesh, whose grid lines are parallel to the axes but need not be equally spaced, with
+    /// the nodes of each column at `x_coords` and each row at `y_coords`. Schemes use finite difference
+    /// weights found from the local spacing, so nodes can be clustered (e.g. with
+    /// [`crate::grid::Stretching`]) without losing the order of accuracy.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 coordinates for either axis, or they aren't increasing.
+    pub fn from_coordinates(x_coords: Vec<f64>, y_coords: Vec<f64>) -> Self {
+        for coords in [&x_coords, &y_coords] {
+            assert!(
+                coords.len() >= 2,
+                "A mesh needs at least 2 nodes in each direction."
+            );
+            assert!(
+                coords.windows(2).all(|w| w[1] > w[0]),
+                "The coordinates of the nodes should be increasing."
+            );
+        }
+
+        let points: Vec<_> = y_coords
+            .iter()
+            .flat_map(|&y| x_coords.iter().map(move |&x| PhysicalCoordinate { x, y }))
+            .collect();
+
+        Self {
+            solution_vals: [0f64].repeat(points.len()),
+            points,
+            width: x_coords.len(),
+            scalings: MeshScaling::Rectilinear(x_coords, y_coords),
+            boundary_conditions: Vec::new(),
+            periodic: [false; 2],
+            metric_derivatives: Vec::new(),
+        }
     }
 
     pub fn from_num_points(
@@ -50,9 +191,14 @@ impl FiniteDiffMesh {
             scalings,
             points,
             width,
+            boundary_conditions: Vec::new(),
+            periodic: [false; 2],
+            metric_derivatives: Vec::new(),
         }
     }
 
+    /// Fills in the values on a boundary once, using a function of the coordinate along it. To keep boundary
+    /// values that the scheme reapplies, use [`Self::set_dirichlet`].
     pub fn fill_dirichlet_bc_vals<F: Fn(f64) -> f64>(&mut self, bound: Boundary, func: F) {
         match bound {
             Boundary::Bottom => {
@@ -84,14 +230,270 @@ impl FiniteDiffMesh {
             Boundary::Right => {
                 let num_rows = self.solution_vals.len() / self.width;
                 for j in 0..num_rows {
-                    let index = self.get_index(num_rows - 1, j);
+                    let index = self.get_index(self.width - 1, j);
                     let PhysicalCoordinate { y, .. } = self.points[index];
-                    self.set_at(num_rows - 1, j, func(y));
+                    self.set_at(self.width - 1, j, func(y));
                 }
             }
         }
     }
 
+    /// Makes a boundary and the one opposite it periodic, so that lookups past one of them wrap around to the
+    /// other. The nodes on the two boundaries are the same points, so the last node along the index is kept
+    /// equal to the first by [`Self::apply_boundary_conditions`].
+    pub fn set_periodic(&mut self, bound: Boundary) {
+        self.periodic[bound.index()] = true;
+    }
+
+    pub fn is_periodic(&self, bound: Boundary) -> bool {
+        self.periodic[bound.index()]
+    }
+
+    /// The value at an offset from node `(i, j)`, wrapping around periodic boundaries.
+    pub fn get_at_offset(&self, i: usize, j: usize, di: isize, dj: isize) -> f64 {
+        let (i, j) = self.offset_indices(i, j, di, dj);
+        self.get_at(i, j)
+    }
+
+    /// Sets the value at an offset from node `(i, j)`, wrapping around periodic boundaries.
+    pub fn set_at_offset(&mut self, i: usize, j: usize, di: isize, dj: isize, value: f64) {
+        let (i, j) = self.offset_indices(i, j, di, dj);
+        self.set_at(i, j, value);
+    }
+
+    /// The indices along `variable` that a stencil reaching from `min_offset` to `max_offset` can be centered
+    /// on. Along a periodic index, this is every node except the last, which is the same point as the first.
+    pub fn centers(
+        &self,
+        variable: Variable,
+        min_offset: isize,
+        max_offset: isize,
+    ) -> Range<usize> {
+        let len = match variable {
+            Variable::X => self.width(),
+            Variable::Y => self.height(),
+            Variable::Z => panic!("2D meshes have no Z index"),
+        };
+
+        if self.periodic[variable.index()] {
+            0..len - 1
+        } else {
+            (-min_offset) as usize..len.saturating_sub(max_offset as usize)
+        }
+    }
+
+    /// The index in the values of the mesh of the node at an offset from node `(i, j)`, wrapping around
+    /// periodic boundaries.
+    pub fn offset_index(&self, i: usize, j: usize, di: isize, dj: isize) -> usize {
+        let (i, j) = self.offset_indices(i, j, di, dj);
+        self.get_index(i, j)
+    }
+
+    fn offset_indices(&self, i: usize, j: usize, di: isize, dj: isize) -> (usize, usize) {
+        let wrap = |index: usize, offset: isize, len: usize, periodic: bool| {
+            let index = index as isize + offset;
+            if periodic {
+                index.rem_euclid(len as isize - 1) as usize
+            } else {
+                index as usize
+            }
+        };
+
+        (
+            wrap(i, di, self.width(), self.periodic[0]),
+            wrap(j, dj, self.height(), self.periodic[1]),
+        )
+    }
+
+    /// Sets the condition on a boundary, replacing any condition that was previously set on it. Unlike
+    /// Dirichlet values, these depend on the solution next to the boundary, so they are applied by the
+    /// generated scheme after each iteration using [`Self::apply_boundary_conditions`].
+    pub fn set_boundary_condition(&mut self, bound: Boundary, condition: BoundaryCondition) {
+        self.boundary_conditions.retain(|(b, _)| *b != bound);
+        self.boundary_conditions
+            .push((bound, StoredCondition::Derivative(condition)));
+    }
+
+    /// Sets the values on a boundary to a function of the physical coordinates `(x, y)` of each node,
+    /// replacing any condition that was previously set on it. Unlike [`Self::fill_dirichlet_bc_vals`], the
+    /// function is kept, and is evaluated again whenever the boundary conditions are applied. When one of
+    /// the coordinates is time, this gives boundary values that vary in time, e.g. an inflow profile
+    /// `|x, t| (1. - x * x) * t.sin()`.
+    pub fn set_dirichlet<F: Fn(f64, f64) -> f64 + 'static>(&mut self, bound: Boundary, func: F) {
+        self.boundary_conditions.retain(|(b, _)| *b != bound);
+        self.boundary_conditions
+            .push((bound, StoredCondition::Dirichlet(Box::new(func))));
+
+        for along in 0..self.boundary_len(bound) {
+            self.apply_condition_at(bound, along);
+        }
+    }
+
+    /// Updates every boundary node that has a condition set on it, using the current values next to it, and
+    /// copies the first node to the last along periodic indices.
+    pub fn apply_boundary_conditions(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        if self.periodic[0] {
+            for j in 0..height {
+                self.set_at(width - 1, j, self.get_at(0, j));
+            }
+        }
+        if self.periodic[1] {
+            for i in 0..width {
+                self.set_at(i, height - 1, self.get_at(i, 0));
+            }
+        }
+
+        for k in 0..self.boundary_conditions.len() {
+            let bound = self.boundary_conditions[k].0;
+
+            for along in 0..self.boundary_len(bound) {
+                self.apply_condition_at(bound, along);
+            }
+        }
+    }
+
+    /// Updates the nodes of row `j` that lie on the left or right boundary. This allows schemes that march
+    /// through the rows to use the boundary values of a row when computing the next one.
+    pub fn apply_boundary_conditions_in_row(&mut self, j: usize) {
+        if self.periodic[0] {
+            self.set_at(self.width() - 1, j, self.get_at(0, j));
+        }
+
+        for k in 0..self.boundary_conditions.len() {
+            let bound = self.boundary_conditions[k].0;
+
+            if matches!(bound, Boundary::Left | Boundary::Right) {
+                self.apply_condition_at(bound, j);
+            }
+        }
+    }
+
+    /// The condition set on a boundary, at the node at position `along` it, as a linear equation
+    /// `sum(coeff * u(i, j)) = rhs` in the values of the nodes going into the domain from the boundary. The
+    /// normal derivative uses a second order one-sided difference. Gives `None` if no condition is set.
+    ///
+    /// This allows implicit schemes to solve for the boundary nodes along with the rest of a row.
+    pub fn boundary_equation(&self, bound: Boundary, along: usize) -> Option<BoundaryEquation> {
+        let (_, condition) = self.boundary_conditions.iter().find(|(b, _)| *b == bound)?;
+
+        // Nodes going into the domain, starting at the boundary
+        let (width, height) = (self.width(), self.height());
+        let node = |k: usize| match bound {
+            Boundary::Left => (k, along),
+            Boundary::Right => (width - 1 - k, along),
+            Boundary::Bottom => (along, k),
+            Boundary::Top => (along, height - 1 - k),
+        };
+
+        let (a, b, g) = match condition {
+            StoredCondition::Dirichlet(func) => {
+                let (i, j) = node(0);
+                let PhysicalCoordinate { x, y } = self.points[self.get_index(i, j)];
+                return Some((vec![((i, j), 1.)], func(x, y)));
+            }
+            StoredCondition::Derivative(BoundaryCondition::Neumann(g)) => (0., 1., *g),
+            StoredCondition::Derivative(BoundaryCondition::Robin { a, b, g }) => (*a, *b, *g),
+        };
+
+        // On a rectilinear mesh, the normal is along the lines of nodes going into the domain, so the normal
+        // derivative is found directly from the distances of the nodes from the boundary
+        let (inward, skew): (Vec<(usize, f64)>, f64) = match &self.scalings {
+            MeshScaling::Rectilinear(..) => {
+                let position = |k: usize| self.points[self.get_index(node(k).0, node(k).1)];
+                let distances: Vec<f64> = (0..3)
+                    .map(|k| (position(k).x - position(0).x).hypot(position(k).y - position(0).y))
+                    .collect();
+                let weights = nonuniform_weights(&distances, 1)
+                    .expect("Distinct nodes approximate the first derivative");
+
+                (weights.into_iter().enumerate().collect(), 0.)
+            }
+            _ => {
+                // The outward normal is along the gradient of the computational coordinate that is constant
+                // on the boundary, so du/dn = -|grad n| du/ds + sign (grad n . grad t) / |grad n| du/dt,
+                // where s goes into the domain, t is the coordinate along the boundary, and sign is positive
+                // on the boundaries where the index increases outwards.
+                let (p_x, p_y, q_x, q_y) = self.get_metrics(node(0).0, node(0).1);
+                let (normal, tangent, sign) = match bound {
+                    Boundary::Left => ((p_x, p_y), (q_x, q_y), -1.),
+                    Boundary::Right => ((p_x, p_y), (q_x, q_y), 1.),
+                    Boundary::Bottom => ((q_x, q_y), (p_x, p_y), -1.),
+                    Boundary::Top => ((q_x, q_y), (p_x, p_y), 1.),
+                };
+                let normal_size = normal.0.hypot(normal.1);
+                let skew = sign * (normal.0 * tangent.0 + normal.1 * tangent.1) / normal_size;
+
+                let inward = derivative_coefficients(0, 3)
+                    .into_iter()
+                    .map(|(offset, coeff)| (offset as usize, normal_size * coeff))
+                    .collect();
+
+                (inward, skew)
+            }
+        };
+
+        let mut terms: Vec<((usize, usize), f64)> = vec![(node(0), a)];
+        let mut add_term =
+            |node: (usize, usize), coeff: f64| match terms.iter_mut().find(|(n, _)| *n == node) {
+                Some((_, c)) => *c += coeff,
+                None => terms.push((node, coeff)),
+            };
+
+        for (k, coeff) in inward {
+            add_term(node(k), -b * coeff);
+        }
+
+        if skew != 0. {
+            let len = self.boundary_len(bound);
+            for (offset, coeff) in derivative_coefficients(along, len) {
+                let along = (along as isize + offset) as usize;
+                let node = match bound {
+                    Boundary::Left => (0, along),
+                    Boundary::Right => (width - 1, along),
+                    Boundary::Bottom => (along, 0),
+                    Boundary::Top => (along, height - 1),
+                };
+                add_term(node, b * skew * coeff);
+            }
+        }
+
+        Some((terms, g))
+    }
+
+    /// The number of nodes along a boundary.
+    fn boundary_len(&self, bound: Boundary) -> usize {
+        match bound {
+            Boundary::Bottom | Boundary::Top => self.width(),
+            Boundary::Left | Boundary::Right => self.height(),
+        }
+    }
+
+    /// Sets the value of the node at position `along` on a boundary so that it satisfies the condition set
+    /// on the boundary.
+    fn apply_condition_at(&mut self, bound: Boundary, along: usize) {
+        let Some((terms, rhs)) = self.boundary_equation(bound, along) else {
+            return;
+        };
+
+        let (node, diagonal) = terms[0];
+        let known: f64 = terms[1..]
+            .iter()
+            .map(|&((i, j), coeff)| coeff * self.get_at(i, j))
+            .sum();
+
+        self.set_at(node.0, node.1, (rhs - known) / diagonal);
+    }
+
+    /// Evaluates a function of the physical coordinates `(x, y)` at every node, giving the values in the
+    /// same order as the nodes are stored, so they can be indexed using [`Self::get_index`].
+    pub fn evaluate<F: Fn(f64, f64) -> f64>(&self, func: F) -> Vec<f64> {
+        self.points
+            .iter()
+            .map(|&PhysicalCoordinate { x, y }| func(x, y))
+            .collect()
+    }
+
     pub fn get_at(&self, i: usize, j: usize) -> f64 {
         let idx = self.get_index(i, j);
         self.solution_vals[idx]
@@ -102,15 +504,142 @@ impl FiniteDiffMesh {
         self.solution_vals[idx] = value;
     }
 
+    /// The values at every node, in the order given by [`Self::get_index`].
+    pub fn values(&self) -> &[f64] {
+        &self.solution_vals
+    }
+
+    /// Replaces the values at every node, which are in the order given by [`Self::get_index`].
+    ///
+    /// # Panics
+    /// If the number of values isn't the number of nodes.
+    pub fn set_values(&mut self, values: &[f64]) {
+        self.solution_vals.copy_from_slice(values);
+    }
+
     pub fn index_iter(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
         let width = self.width;
         (0..self.solution_vals.len()).map(move |i| Self::make_indices(width, i))
     }
 
+    /// The number of nodes along the first index.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of nodes along the second index.
+    pub fn height(&self) -> usize {
+        self.solution_vals.len() / self.width
+    }
+
+    /// The shortest distance between neighbouring nodes along the first and second index respectively, which
+    /// limits the stable time step of explicit schemes. On a simple grid, these are `dx` and `dy`.
+    pub fn min_spacing(&self) -> [f64; 2] {
+        if let MeshScaling::SimpleGrid(dx, dy) = self.scalings {
+            return [dx, dy];
+        }
+
+        let distance = |a: usize, b: usize| {
+            let (p, q) = (&self.points[a], &self.points[b]);
+            (q.x - p.x).hypot(q.y - p.y)
+        };
+
+        let mut spacing = [f64::INFINITY; 2];
+        for (i, j) in self.index_iter() {
+            let index = self.get_index(i, j);
+            if i + 1 < self.width() {
+                spacing[0] = spacing[0].min(distance(index, self.get_index(i + 1, j)));
+            }
+            if j + 1 < self.height() {
+                spacing[1] = spacing[1].min(distance(index, self.get_index(i, j + 1)));
+            }
+        }
+
+        spacing
+    }
+
     pub fn get_scaling(&self) -> &MeshScaling {
         &self.scalings
     }
 
+    /// The metrics `(dp/dx, dp/dy, dq/dx, dq/dy)` at a node, where `p` and `q` are the computational
+    /// coordinates. On a simple grid, these are the same at every node.
+    pub fn get_metrics(&self, i: usize, j: usize) -> (f64, f64, f64, f64) {
+        match &self.scalings {
+            MeshScaling::SimpleGrid(dx, dy) => (1. / dx, 0., 0., 1. / dy),
+            MeshScaling::Rectilinear(x_coords, y_coords) => (
+                1. / first_derivative(|k| x_coords[k], i, x_coords.len()),
+                0.,
+                0.,
+                1. / first_derivative(|k| y_coords[k], j, y_coords.len()),
+            ),
+            MeshScaling::ComplexPhysDomain(metrics) => metrics[self.get_index(i, j)],
+        }
+    }
+
+    /// The second derivatives of the computational coordinates at a node, in the order
+    /// `[p_xx, p_xy, p_yy, q_xx, q_xy, q_yy]`. These are zero on a simple grid.
+    pub fn get_metric_derivatives(&self, i: usize, j: usize) -> [f64; 6] {
+        match &self.scalings {
+            MeshScaling::SimpleGrid(..) => [0.; 6],
+            MeshScaling::Rectilinear(x_coords, y_coords) => {
+                // p_xx = d(p_x)/dp p_x, where p_x = 1 / x_p
+                let second = |coords: &[f64], k: usize| {
+                    let len = coords.len();
+                    let metric = |k: usize| 1. / first_derivative(|l| coords[l], k, len);
+                    first_derivative(metric, k, len) * metric(k)
+                };
+
+                [second(x_coords, i), 0., 0., 0., 0., second(y_coords, j)]
+            }
+            MeshScaling::ComplexPhysDomain(_) => self.metric_derivatives[self.get_index(i, j)],
+        }
+    }
+
+    /// The weights of the nodes at `offsets` along a variable from node `index` in the approximation of the
+    /// derivative of the given order, found from the coordinates of the nodes. Along a periodic index,
+    /// offsets past the end of the mesh wrap around. This is meant for meshes whose lines are parallel to
+    /// the axes, as on other meshes the coordinates along a line of nodes vary in both directions.
+    ///
+    /// # Panics
+    /// If there are too few offsets for the derivative, or two of them are at the same node.
+    pub fn taylor_weights<const N: usize>(
+        &self,
+        variable: Variable,
+        index: usize,
+        offsets: [isize; N],
+        order: usize,
+    ) -> [f64; N] {
+        let center = self.coordinate(variable, index as isize);
+        let distances: Vec<f64> = offsets
+            .iter()
+            .map(|offset| self.coordinate(variable, index as isize + offset) - center)
+            .collect();
+
+        nonuniform_weights(&distances, order)
+            .expect("The offsets should be distinct nodes, enough to approximate the derivative")
+            .try_into()
+            .unwrap()
+    }
+
+    /// The coordinate along a variable of the nodes at `index` along it. On a periodic axis, the index can be
+    /// outside the mesh, where the coordinate is offset by the length of the domain for each wrap.
+    fn coordinate(&self, variable: Variable, index: isize) -> f64 {
+        let (len, at): (usize, &dyn Fn(usize) -> f64) = match variable {
+            Variable::X => (self.width(), &|k| self.points[k].x),
+            Variable::Y => (self.height(), &|k| self.points[k * self.width].y),
+            Variable::Z => panic!("2D meshes have no Z index"),
+        };
+
+        if self.periodic[variable.index()] {
+            let period = (len - 1) as isize;
+            let wraps = index.div_euclid(period) as f64;
+            at(index.rem_euclid(period) as usize) + wraps * (at(len - 1) - at(0))
+        } else {
+            at(index as usize)
+        }
+    }
+
     pub fn save_coords(&self, file: &str) {
         let mut string = String::new();
         for i in 0..self.solution_vals.len() {
@@ -131,7 +660,7 @@ impl FiniteDiffMesh {
         std::fs::write(file, bytes).expect("Writing failed");
     }
 
-    fn get_index(&self, i: usize, j: usize) -> usize {
+    pub fn get_index(&self, i: usize, j: usize) -> usize {
         i + j * self.width
     }
 
@@ -142,14 +671,55 @@ impl FiniteDiffMesh {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PhysicalCoordinate {
-    x: f64,
-    y: f64,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+impl PhysicalCoordinate {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// The offsets of the nodes used to approximate a first derivative at node `k` of a line of `len` nodes.
+/// Central differences are used inside the line and one-sided differences at its ends.
+fn derivative_stencil(k: usize, len: usize) -> &'static [(isize, isize)] {
+    if len == 2 {
+        if k == 0 {
+            &[(0, 0), (1, 0)]
+        } else {
+            &[(-1, 0), (0, 0)]
+        }
+    } else if k == 0 {
+        &[(0, 0), (1, 0), (2, 0)]
+    } else if k == len - 1 {
+        &[(-2, 0), (-1, 0), (0, 0)]
+    } else {
+        &[(-1, 0), (0, 0), (1, 0)]
+    }
+}
+
+/// The coefficients of a first derivative at node `k` of a line of `len` nodes, with the offset of each node.
+fn derivative_coefficients(k: usize, len: usize) -> Vec<(isize, f64)> {
+    TaylorTable::new(derivative_stencil(k, len), Variable::X)
+        .get_coefficients(1)
+        .expect("Stencils with at least 2 nodes approximate the first derivative")
+}
+
+/// The derivative of a value along a line of nodes at node `k`, where `len` is the number of nodes on the
+/// line and nodes are spaced by 1.
+fn first_derivative<F: Fn(usize) -> f64>(value: F, k: usize, len: usize) -> f64 {
+    derivative_coefficients(k, len)
+        .into_iter()
+        .map(|(offset, coeff)| coeff * value((k as isize + offset) as usize))
+        .sum()
 }
 
 /// Identifies a boundary of the computational domain. Bottom is the line where the first coordinate is zero, top is where
 /// the first coordinate is highest. Left and right are similarly defined but w.r.t. the second coordinate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Boundary {
     Top,
     Bottom,
@@ -157,8 +727,303 @@ pub enum Boundary {
     Right,
 }
 
+impl Boundary {
+    /// The index that is constant along the boundary.
+    fn index(&self) -> usize {
+        match self {
+            Self::Left | Self::Right => 0,
+            Self::Bottom | Self::Top => 1,
+        }
+    }
+}
+
+/// A boundary condition involving the derivative of the solution along the outward normal of the boundary,
+/// `du/dn`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundaryCondition {
+    /// `du/dn = g`. For example, an insulated wall is `Neumann(0.)`.
+    Neumann(f64),
+    /// `a u + b du/dn = g`, such as convective heat transfer at a wall.
+    Robin { a: f64, b: f64, g: f64 },
+}
+
+/// A condition kept by the mesh to be reapplied to a boundary.
+enum StoredCondition {
+    Derivative(BoundaryCondition),
+    Dirichlet(Box<dyn Fn(f64, f64) -> f64>),
+}
+
+/// A linear equation `sum(coeff * u(i, j)) = rhs` in the values of some nodes, given as the list of nodes
+/// with their coefficients, and the right hand side.
+pub type BoundaryEquation = (Vec<((usize, usize), f64)>, f64);
+
 pub enum MeshScaling {
     /// Values are dx and dy
     SimpleGrid(f64, f64),
+    /// Values are the x coordinates of the columns of nodes and the y coordinates of the rows, which need
+    /// not be equally spaced.
+    Rectilinear(Vec<f64>, Vec<f64>),
+    /// Values are dp/dx, dp/dy, dq/dx and dq/dy at each node, where p and q are the computational coordinates
+    /// along the first and second index. Nodes are in the same order as the solution values.
     ComplexPhysDomain(Vec<(f64, f64, f64, f64)>),
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Boundary, BoundaryCondition, FiniteDiffMesh, MeshScaling, PhysicalCoordinate};
+    use crate::algebra::Variable;
+
+    /// A mesh over `[0, 1] x [0, 2]` with `u = x^2 + 3y`, for which the one-sided differences are exact.
+    fn quadratic_mesh() -> FiniteDiffMesh {
+        let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 2., 11, 21);
+        let values = mesh.evaluate(|x, y| x * x + 3. * y);
+
+        for (i, j) in mesh.index_iter() {
+            let idx = mesh.get_index(i, j);
+            mesh.set_at(i, j, values[idx]);
+        }
+
+        mesh
+    }
+
+    #[test]
+    fn fill_right() {
+        // The mesh is taller than it is wide, so the last column isn't at the number of rows
+        let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 2., 11, 21);
+        mesh.fill_dirichlet_bc_vals(Boundary::Right, |y| y + 1.);
+
+        for j in 0..21 {
+            assert_eq!(mesh.get_at(10, j), j as f64 * 0.1 + 1.);
+            assert_eq!(mesh.get_at(9, j), 0.);
+        }
+    }
+
+    #[test]
+    fn neumann() {
+        let mut mesh = quadratic_mesh();
+        let expected = mesh.get_at(10, 5);
+
+        mesh.set_at(10, 5, 100.);
+        mesh.set_at(4, 0, 100.);
+        // Outward normals point in +x on the right and -y on the bottom
+        mesh.set_boundary_condition(Boundary::Right, BoundaryCondition::Neumann(2.));
+        mesh.set_boundary_condition(Boundary::Bottom, BoundaryCondition::Neumann(-3.));
+        mesh.apply_boundary_conditions();
+
+        assert!((mesh.get_at(10, 5) - expected).abs() < 1e-12);
+        assert!((mesh.get_at(4, 0) - 0.16).abs() < 1e-12);
+    }
+
+    #[test]
+    fn periodic() {
+        let mut mesh = quadratic_mesh();
+        mesh.set_periodic(Boundary::Right);
+
+        assert!(mesh.is_periodic(Boundary::Left));
+        assert!(!mesh.is_periodic(Boundary::Top));
+
+        // The period is 10 nodes, as the first and last node are the same point
+        assert_eq!(mesh.get_at_offset(0, 3, -1, 0), mesh.get_at(9, 3));
+        assert_eq!(mesh.get_at_offset(9, 3, 2, 1), mesh.get_at(1, 4));
+        assert_eq!(mesh.centers(Variable::X, -1, 1), 0..10);
+        assert_eq!(mesh.centers(Variable::Y, -1, 1), 1..20);
+
+        mesh.apply_boundary_conditions();
+        assert_eq!(mesh.get_at(10, 3), mesh.get_at(0, 3));
+    }
+
+    #[test]
+    fn dirichlet_reevaluated() {
+        let mut mesh = quadratic_mesh();
+        mesh.set_boundary_condition(Boundary::Top, BoundaryCondition::Neumann(0.));
+        mesh.set_dirichlet(Boundary::Top, |x, y| x + y);
+        assert_eq!(mesh.get_at(5, 20), 2.5);
+
+        // The function replaced the Neumann condition, and overwrites values set after it
+        mesh.set_at(5, 20, 100.);
+        mesh.apply_boundary_conditions();
+        assert_eq!(mesh.get_at(5, 20), 2.5);
+        assert_eq!(
+            mesh.boundary_equation(Boundary::Top, 2),
+            Some((vec![((2, 20), 1.)], 2.2))
+        );
+    }
+
+    /// The metrics at each node of a mesh from [`FiniteDiffMesh::from_physical_domain`].
+    fn metrics(points: Vec<Vec<PhysicalCoordinate>>) -> Vec<(f64, f64, f64, f64)> {
+        let lines: Vec<&[PhysicalCoordinate]> = points.iter().map(Vec::as_slice).collect();
+        let mesh = FiniteDiffMesh::from_physical_domain(&lines);
+
+        match mesh.get_scaling() {
+            MeshScaling::ComplexPhysDomain(metrics) => metrics.clone(),
+            _ => panic!("Expected a curvilinear mesh"),
+        }
+    }
+
+    #[test]
+    fn sheared_metrics() {
+        // x = 2p + q, y = 0.5q, so p = (x - 2y) / 2 and q = 2y
+        let points = (0..4)
+            .map(|q| {
+                (0..5)
+                    .map(|p| PhysicalCoordinate::new(2. * p as f64 + q as f64, 0.5 * q as f64))
+                    .collect()
+            })
+            .collect();
+
+        for (p_x, p_y, q_x, q_y) in metrics(points) {
+            assert!((p_x - 0.5).abs() < 1e-12);
+            assert!((p_y + 1.).abs() < 1e-12);
+            assert!(q_x.abs() < 1e-12);
+            assert!((q_y - 2.).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn polar_metrics() {
+        // An annulus with r = 1 + p dr and theta = q dtheta, for which p_x = cos(theta) / dr and
+        // q_x = -sin(theta) / (r dtheta)
+        let (dr, dtheta) = (0.01, 0.01);
+        let points = (0..20)
+            .map(|q| {
+                let theta = q as f64 * dtheta;
+                (0..20)
+                    .map(|p| {
+                        let r = 1. + p as f64 * dr;
+                        PhysicalCoordinate::new(r * theta.cos(), r * theta.sin())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let metrics = metrics(points);
+        let (r, theta) = (1. + 5. * dr, 7. * dtheta);
+        let (p_x, _, q_x, _) = metrics[5 + 7 * 20];
+
+        assert!((p_x - theta.cos() / dr).abs() < 1e-2);
+        assert!((q_x + theta.sin() / (r * dtheta)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn polar_metric_derivatives() {
+        let (dr, dtheta) = (0.01, 0.01);
+        let lines: Vec<Vec<_>> = (0..20)
+            .map(|q| {
+                let theta = q as f64 * dtheta;
+                (0..20)
+                    .map(|p| {
+                        let r = 1. + p as f64 * dr;
+                        PhysicalCoordinate::new(r * theta.cos(), r * theta.sin())
+                    })
+                    .collect()
+            })
+            .collect();
+        let lines: Vec<&[PhysicalCoordinate]> = lines.iter().map(Vec::as_slice).collect();
+        let mesh = FiniteDiffMesh::from_physical_domain(&lines);
+
+        // p_xx = r_xx / dr = sin^2(theta) / (r dr), and p_yy = cos^2(theta) / (r dr)
+        let (r, theta) = (1. + 5. * dr, 7. * dtheta);
+        let [p_xx, _, p_yy, ..] = mesh.get_metric_derivatives(5, 7);
+
+        assert!((p_xx - theta.sin().powi(2) / (r * dr)).abs() < 1e-2);
+        assert!((p_yy - theta.cos().powi(2) / (r * dr)).abs() < 1e-1);
+    }
+
+    #[test]
+    fn skewed_neumann() {
+        // x = 2p + q, y = 0.5q, where the left boundary isn't orthogonal to the lines of constant q
+        let lines: Vec<Vec<_>> = (0..6)
+            .map(|q| {
+                (0..6)
+                    .map(|p| PhysicalCoordinate::new(2. * p as f64 + q as f64, 0.5 * q as f64))
+                    .collect()
+            })
+            .collect();
+        let lines: Vec<&[PhysicalCoordinate]> = lines.iter().map(Vec::as_slice).collect();
+        let mut mesh = FiniteDiffMesh::from_physical_domain(&lines);
+
+        let values = mesh.evaluate(|x, y| 3. * x + 2. * y);
+        for (i, j) in mesh.index_iter() {
+            mesh.set_at(i, j, values[mesh.get_index(i, j)]);
+        }
+        let expected = mesh.get_at(0, 3);
+
+        // The outward normal of the left boundary is (-0.5, 1) / sqrt(1.25)
+        mesh.set_boundary_condition(
+            Boundary::Left,
+            BoundaryCondition::Neumann(0.5 / 1.25f64.sqrt()),
+        );
+        mesh.set_at(0, 3, 100.);
+        mesh.apply_boundary_conditions_in_row(3);
+
+        assert!((mesh.get_at(0, 3) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn robin_in_row() {
+        let mut mesh = quadratic_mesh();
+        // At x = 0, u = 3y and du/dn = 0, so 2u + du/dn = 6y
+        mesh.set_boundary_condition(
+            Boundary::Left,
+            BoundaryCondition::Robin {
+                a: 2.,
+                b: 1.,
+                g: 6.,
+            },
+        );
+        mesh.set_at(0, 10, 100.);
+        mesh.set_at(0, 11, 100.);
+        mesh.apply_boundary_conditions_in_row(10);
+
+        assert!((mesh.get_at(0, 10) - 3.).abs() < 1e-12);
+        assert_eq!(mesh.get_at(0, 11), 100.);
+    }
+
+    #[test]
+    fn rectilinear_weights() {
+        // Nodes clustered towards x = 0
+        let x_coords: Vec<f64> = (0..11).map(|i| (i as f64 / 10.).powi(2)).collect();
+        let y_coords: Vec<f64> = (0..5).map(|j| j as f64).collect();
+        let mut mesh = FiniteDiffMesh::from_coordinates(x_coords.clone(), y_coords);
+        let values = mesh.evaluate(|x, y| x * x + y);
+        for (i, j) in mesh.index_iter() {
+            let idx = mesh.get_index(i, j);
+            mesh.set_at(i, j, values[idx]);
+        }
+
+        // Second derivatives of quadratics are exact on any spacing
+        let weights = mesh.taylor_weights(Variable::X, 3, [-1, 0, 1], 2);
+        let approx: f64 = weights
+            .iter()
+            .zip(-1..=1)
+            .map(|(w, di)| w * mesh.get_at_offset(3, 2, di, 0))
+            .sum();
+        assert!((approx - 2.).abs() < 1e-9);
+
+        // The one-sided normal derivative uses the actual spacing too
+        mesh.set_at(0, 2, 100.);
+        mesh.set_boundary_condition(Boundary::Left, BoundaryCondition::Neumann(0.));
+        mesh.apply_boundary_conditions();
+        assert!((mesh.get_at(0, 2) - 2.).abs() < 1e-9);
+
+        let (p_x, _, _, q_y) = mesh.get_metrics(10, 2);
+        assert!((p_x - 5.).abs() < 1e-9);
+        assert_eq!(q_y, 1.);
+
+        // On a periodic axis, the distances wrap around the length of the domain
+        mesh.set_periodic(Boundary::Bottom);
+        let weights = mesh.taylor_weights(Variable::Y, 0, [-1, 0, 1], 1);
+        assert_eq!(weights, [-0.5, 0., 0.5]);
+    }
+
+    #[test]
+    fn min_spacing() {
+        let mesh = FiniteDiffMesh::from_coordinates(vec![0., 0.5, 0.6, 1.], vec![0., 0.25, 1.]);
+        let [dx, dy] = mesh.min_spacing();
+        assert!((dx - 0.1).abs() < 1e-12);
+        assert_eq!(dy, 0.25);
+
+        let mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 2., 11, 5);
+        assert_eq!(mesh.min_spacing(), [0.1, 0.5]);
+    }
+}
@@ -0,0 +1,292 @@
+use std::ops::Range;
+
+use crate::algebra::Variable;
+
+/// A uniform mesh with three indices, for problems with three independent variables. One of these is often
+/// time, e.g. `(x, y, t)` for transient problems in 2D, where each layer of constant third index is the
+/// solution at one time.
+pub struct FiniteDiffMesh3D {
+    solution_vals: Vec<f64>,
+
+    /// The number of nodes along each index.
+    dims: [usize; 3],
+    /// The coordinates of the first node, and the spacing of the nodes along each index.
+    min: [f64; 3],
+    spacing: [f64; 3],
+
+    /// Dirichlet conditions that are reapplied to the nodes of a face, in the order they were set.
+    boundary_conditions: Vec<(Face, DirichletFn)>,
+}
+
+impl FiniteDiffMesh3D {
+    /// A mesh with `num[n]` nodes evenly spaced from `min[n]` to `max[n]` along each index.
+    ///
+    /// # Panics
+    /// If there are fewer than 2 nodes along any index.
+    pub fn from_num_points(min: [f64; 3], max: [f64; 3], num: [usize; 3]) -> Self {
+        assert!(
+            num.iter().all(|&n| n >= 2),
+            "A mesh needs at least 2 nodes in each direction."
+        );
+
+        let spacing: [f64; 3] = std::array::from_fn(|n| (max[n] - min[n]) / ((num[n] - 1) as f64));
+
+        Self {
+            solution_vals: [0f64].repeat(num.iter().product()),
+            dims: num,
+            min,
+            spacing,
+            boundary_conditions: Vec::new(),
+        }
+    }
+
+    pub fn get_at(&self, i: usize, j: usize, k: usize) -> f64 {
+        self.solution_vals[self.get_index(i, j, k)]
+    }
+
+    pub fn set_at(&mut self, i: usize, j: usize, k: usize, value: f64) {
+        let idx = self.get_index(i, j, k);
+        self.solution_vals[idx] = value;
+    }
+
+    /// The value at an offset from node `(i, j, k)`.
+    pub fn get_at_offset(
+        &self,
+        i: usize,
+        j: usize,
+        k: usize,
+        di: isize,
+        dj: isize,
+        dk: isize,
+    ) -> f64 {
+        let (i, j, k) = Self::offset_indices(i, j, k, di, dj, dk);
+        self.get_at(i, j, k)
+    }
+
+    /// Sets the value at an offset from node `(i, j, k)`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_at_offset(
+        &mut self,
+        i: usize,
+        j: usize,
+        k: usize,
+        di: isize,
+        dj: isize,
+        dk: isize,
+        value: f64,
+    ) {
+        let (i, j, k) = Self::offset_indices(i, j, k, di, dj, dk);
+        self.set_at(i, j, k, value);
+    }
+
+    fn offset_indices(
+        i: usize,
+        j: usize,
+        k: usize,
+        di: isize,
+        dj: isize,
+        dk: isize,
+    ) -> (usize, usize, usize) {
+        (
+            (i as isize + di) as usize,
+            (j as isize + dj) as usize,
+            (k as isize + dk) as usize,
+        )
+    }
+
+    /// The indices along `variable` that a stencil reaching from `min_offset` to `max_offset` can be centered
+    /// on.
+    pub fn centers(
+        &self,
+        variable: Variable,
+        min_offset: isize,
+        max_offset: isize,
+    ) -> Range<usize> {
+        let len = self.dims[variable.index()];
+        (-min_offset) as usize..len.saturating_sub(max_offset as usize)
+    }
+
+    /// Sets the values on a face to a function of the physical coordinates `(x, y, z)` of each node,
+    /// replacing any condition that was previously set on it. The function is kept, and is evaluated again
+    /// whenever the boundary conditions are applied. When the third index is time, the condition on
+    /// [`Face::Back`] is the initial condition.
+    pub fn set_dirichlet<F: Fn(f64, f64, f64) -> f64 + 'static>(&mut self, face: Face, func: F) {
+        self.boundary_conditions.retain(|(f, _)| *f != face);
+        self.boundary_conditions.push((face, Box::new(func)));
+
+        self.apply_condition(self.boundary_conditions.len() - 1, None);
+    }
+
+    /// Updates every node on a face that has a condition set on it.
+    pub fn apply_boundary_conditions(&mut self) {
+        for n in 0..self.boundary_conditions.len() {
+            self.apply_condition(n, None);
+        }
+    }
+
+    /// Updates the nodes of layer `k` (the nodes with third index `k`) that lie on a face with a condition.
+    /// This allows schemes that march through the layers to use the boundary values of a layer when
+    /// computing the next one.
+    pub fn apply_boundary_conditions_in_layer(&mut self, k: usize) {
+        for n in 0..self.boundary_conditions.len() {
+            self.apply_condition(n, Some(k));
+        }
+    }
+
+    /// Applies the `n`th condition that was set, either on the whole face or only on one layer of it.
+    fn apply_condition(&mut self, n: usize, layer: Option<usize>) {
+        let face = self.boundary_conditions[n].0;
+        let [width, height, depth] = self.dims;
+
+        let mut ranges = [0..width, 0..height, layer.map_or(0..depth, |k| k..k + 1)];
+        let axis = face.index();
+        let at = if face.is_start() {
+            0
+        } else {
+            self.dims[axis] - 1
+        };
+
+        if !ranges[axis].contains(&at) {
+            return;
+        }
+        ranges[axis] = at..at + 1;
+
+        let [is, js, ks] = ranges;
+        for k in ks {
+            for j in js.clone() {
+                for i in is.clone() {
+                    let [x, y, z] = self.coordinates(i, j, k);
+                    let value = (self.boundary_conditions[n].1)(x, y, z);
+                    self.set_at(i, j, k, value);
+                }
+            }
+        }
+    }
+
+    /// The physical coordinates of node `(i, j, k)`.
+    pub fn coordinates(&self, i: usize, j: usize, k: usize) -> [f64; 3] {
+        let indices = [i, j, k];
+        std::array::from_fn(|n| self.min[n] + indices[n] as f64 * self.spacing[n])
+    }
+
+    /// Evaluates a function of the physical coordinates `(x, y, z)` at every node, giving the values in the
+    /// same order as the nodes are stored, so they can be indexed using [`Self::get_index`].
+    pub fn evaluate<F: Fn(f64, f64, f64) -> f64>(&self, func: F) -> Vec<f64> {
+        self.index_iter()
+            .map(|(i, j, k)| {
+                let [x, y, z] = self.coordinates(i, j, k);
+                func(x, y, z)
+            })
+            .collect()
+    }
+
+    /// The values of the nodes with third index `k`, in the same order as a 2D mesh with the first two
+    /// indices would store them. When the third index is time, this is the solution at one time.
+    pub fn layer(&self, k: usize) -> Vec<f64> {
+        let len = self.dims[0] * self.dims[1];
+        self.solution_vals[k * len..(k + 1) * len].to_vec()
+    }
+
+    pub fn index_iter(&self) -> impl Iterator<Item = (usize, usize, usize)> + use<> {
+        let [width, height, _] = self.dims;
+        (0..self.solution_vals.len())
+            .map(move |n| (n % width, (n / width) % height, n / (width * height)))
+    }
+
+    /// The number of nodes along each index.
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    /// The spacing of the nodes along each index, `[dx, dy, dz]`.
+    pub fn spacing(&self) -> [f64; 3] {
+        self.spacing
+    }
+
+    pub fn save_values(&self, file: &str) {
+        let bytes: Vec<u8> = self
+            .solution_vals
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        std::fs::write(file, bytes).expect("Writing failed");
+    }
+
+    pub fn get_index(&self, i: usize, j: usize, k: usize) -> usize {
+        i + self.dims[0] * (j + self.dims[1] * k)
+    }
+}
+
+/// The values on a face, as a function of the physical coordinates.
+type DirichletFn = Box<dyn Fn(f64, f64, f64) -> f64>;
+
+/// Identifies a face of a 3D mesh. Left and right are where the first index is lowest and highest, bottom
+/// and top are similarly defined w.r.t. the second index, and back and front w.r.t. the third.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Face {
+    Left,
+    Right,
+    Bottom,
+    Top,
+    Back,
+    Front,
+}
+
+impl Face {
+    /// The index that is constant on the face.
+    fn index(&self) -> usize {
+        match self {
+            Self::Left | Self::Right => 0,
+            Self::Bottom | Self::Top => 1,
+            Self::Back | Self::Front => 2,
+        }
+    }
+
+    /// Whether the face is where its index is zero.
+    fn is_start(&self) -> bool {
+        matches!(self, Self::Left | Self::Bottom | Self::Back)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Face, FiniteDiffMesh3D};
+    use crate::algebra::Variable;
+
+    #[test]
+    fn indexing() {
+        let mut mesh = FiniteDiffMesh3D::from_num_points([0., 0., 0.], [1., 2., 3.], [3, 4, 5]);
+        assert_eq!(mesh.spacing(), [0.5, 2. / 3., 0.75]);
+
+        for (n, (i, j, k)) in mesh.index_iter().enumerate() {
+            assert_eq!(mesh.get_index(i, j, k), n);
+        }
+
+        mesh.set_at(2, 1, 3, 4.);
+        assert_eq!(mesh.get_at_offset(1, 2, 2, 1, -1, 1), 4.);
+        assert_eq!(mesh.centers(Variable::Z, -1, 0), 1..5);
+        assert_eq!(mesh.centers(Variable::X, -1, 1), 1..2);
+    }
+
+    #[test]
+    fn faces() {
+        let mut mesh = FiniteDiffMesh3D::from_num_points([0., 0., 0.], [1., 1., 1.], [3, 3, 4]);
+
+        mesh.set_dirichlet(Face::Back, |x, y, _| x + y);
+        mesh.set_dirichlet(Face::Right, |_, _, z| z);
+
+        // The condition set last is applied last where faces meet
+        assert_eq!(mesh.get_at(1, 2, 0), 1.5);
+        assert_eq!(mesh.get_at(2, 2, 0), 0.);
+        assert_eq!(mesh.get_at(2, 1, 3), 1.);
+        assert_eq!(mesh.get_at(1, 1, 3), 0.);
+
+        mesh.set_at(2, 0, 2, 10.);
+        mesh.set_at(2, 0, 1, 10.);
+        mesh.apply_boundary_conditions_in_layer(2);
+
+        assert!((mesh.get_at(2, 0, 2) - 2. / 3.).abs() < 1e-12);
+        assert_eq!(mesh.get_at(2, 0, 1), 10.);
+        assert_eq!(mesh.layer(0)[5], 0.);
+    }
+}
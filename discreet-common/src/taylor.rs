@@ -6,22 +6,57 @@ fn fact(n: usize) -> usize {
     (1..=n).product()
 }
 
+/// A node of a stencil, given by its offset from the center of the stencil along each index of the mesh.
+pub trait StencilNode {
+    fn offset(&self) -> Vec<isize>;
+}
+
+impl StencilNode for (isize, isize) {
+    fn offset(&self) -> Vec<isize> {
+        vec![self.0, self.1]
+    }
+}
+
+impl StencilNode for (isize, isize, isize) {
+    fn offset(&self) -> Vec<isize> {
+        vec![self.0, self.1, self.2]
+    }
+}
+
+impl StencilNode for Vec<isize> {
+    fn offset(&self) -> Vec<isize> {
+        self.clone()
+    }
+}
+
 pub struct TaylorTable {
     /// Columns of the inverted Taylor table matrix. Its columns are simply
     /// the coefficients for each order derivative, zero-indexed.
     cols: Vec<Vec<f64>>,
     variable: Variable,
     stencil: Vec<isize>,
+    /// Number of indices of the mesh that the stencil is on.
+    dimensions: usize,
 }
 
 impl TaylorTable {
-    pub fn new(stencil: &[(isize, isize)], variable: Variable) -> Self {
-        let filter_fn: fn((isize, isize)) -> Option<isize> = match variable {
-            Variable::X => |(x, y)| if y == 0 { Some(x) } else { None },
-            Variable::Y => |(x, y)| if x == 0 { Some(y) } else { None },
-        };
+    /// Builds the table from the nodes of the stencil on the axis of `variable`, i.e. those that are only
+    /// offset along its index.
+    pub fn new<N: StencilNode>(stencil: &[N], variable: Variable) -> Self {
+        let axis = variable.index();
+        let offsets: Vec<Vec<isize>> = stencil.iter().map(StencilNode::offset).collect();
+        let dimensions = offsets.first().map_or(axis + 1, Vec::len);
 
-        let stencil: Vec<isize> = stencil.iter().copied().filter_map(filter_fn).collect();
+        let stencil: Vec<isize> = offsets
+            .iter()
+            .filter(|offset| {
+                offset
+                    .iter()
+                    .enumerate()
+                    .all(|(index, o)| index == axis || *o == 0)
+            })
+            .filter_map(|offset| offset.get(axis).copied())
+            .collect();
 
         let size = stencil.len();
         let mut cols = Vec::with_capacity(size);
@@ -45,30 +80,212 @@ impl TaylorTable {
             cols,
             variable,
             stencil,
+            dimensions,
         }
     }
 
     pub fn get_scheme(&self, derivative_order: usize) -> Option<MeshExpr> {
+        let terms = self
+            .get_coefficients(derivative_order)?
+            .into_iter()
+            .filter(|&(_, coeff)| coeff != 0.)
+            .map(|(offset, coeff)| {
+                let mut node = vec![0; self.dimensions];
+                node[self.variable.index()] = offset;
+
+                MeshExpr::Prod(vec![MeshExpr::Constant(coeff), MeshExpr::AtOffset(node)])
+            })
+            .collect();
+
+        Some(MeshExpr::Sum(terms))
+    }
+
+    /// The weight of each node of the stencil (given by its offset along the variable) in the approximation
+    /// of the derivative, for nodes spaced by 1.
+    pub fn get_coefficients(&self, derivative_order: usize) -> Option<Vec<(isize, f64)>> {
         let col = self.cols.get(derivative_order)?;
-        let size = self.cols.len();
+
+        Some(
+            self.stencil
+                .iter()
+                .copied()
+                .zip(col.iter().copied())
+                .collect(),
+        )
+    }
+}
+
+/// The weights of nodes at the given signed distances from the center in the approximation of the derivative
+/// of the given order, for nodes that aren't equally spaced. Gives `None` if there are too few nodes for
+/// the derivative, or two nodes are at the same place.
+pub fn nonuniform_weights(distances: &[f64], derivative_order: usize) -> Option<Vec<f64>> {
+    // The table is built for distances scaled to at most 1, so that it is well conditioned for small spacings
+    let scale = distances.iter().fold(0f64, |max, d| max.max(d.abs()));
+    let size = distances.len();
+    if derivative_order >= size || scale == 0. {
+        return None;
+    }
+
+    let cols = distances
+        .iter()
+        .map(|d| {
+            (0..size)
+                .map(|j| (d / scale).powi(j as i32) / (fact(j) as f64))
+                .collect()
+        })
+        .collect();
+
+    let cols = SquareMat::new(cols).try_invert()?.get_cols();
+    let factor = scale.powi(derivative_order as i32);
+    Some(cols[derivative_order].iter().map(|w| w / factor).collect())
+}
+
+/// A Taylor table built from every node of a stencil, rather than just the nodes on one axis. This allows
+/// approximating mixed derivatives such as `u_xy`, on meshes with any number of dimensions.
+pub struct TaylorTableND {
+    /// The powers of the Taylor series terms `p^m q^n ... / (m! n! ...)` used as rows of the table, with
+    /// one power for each index of the mesh.
+    terms: Vec<Vec<usize>>,
+    /// Columns of the inverted Taylor table matrix, corresponding to the terms in `terms`. Empty if
+    /// the stencil can't be used to build a table.
+    cols: Vec<Vec<f64>>,
+    stencil: Vec<Vec<isize>>,
+}
+
+impl TaylorTableND {
+    pub fn new<N: StencilNode>(stencil: &[N]) -> Self {
+        let stencil: Vec<Vec<isize>> = stencil.iter().map(StencilNode::offset).collect();
+        let size = stencil.len();
+
+        let terms = Self::independent_terms(&stencil);
+
+        let cols = if terms.len() == size {
+            let mut cols = Vec::with_capacity(size);
+
+            for offset in &stencil {
+                let col = terms
+                    .iter()
+                    .map(|powers| Self::term_value(offset, powers))
+                    .collect();
+
+                cols.push(col);
+            }
+
+            match SquareMat::new(cols).try_invert() {
+                Some(inv) => inv.get_cols(),
+                None => vec![],
+            }
+        } else {
+            vec![]
+        };
+
+        Self {
+            terms,
+            cols,
+            stencil,
+        }
+    }
+
+    /// Selects the Taylor series terms to be matched, in order of increasing total degree. Each term is only
+    /// included if the stencil can distinguish it from the previous ones (for example, on the stencil
+    /// `[(-1, 0), (0, 0), (1, 0)]`, `p^3` can't be distinguished from `p`).
+    fn independent_terms(stencil: &[Vec<isize>]) -> Vec<Vec<usize>> {
+        let size = stencil.len();
+        let dimensions = stencil.first().map_or(0, Vec::len);
 
         let mut terms = Vec::with_capacity(size);
+        // Rows of the table reduced against each other, used to check for linear independence.
+        let mut basis: Vec<(usize, Vec<f64>)> = Vec::with_capacity(size);
 
-        for (i, &coeff) in col.iter().enumerate() {
-            if coeff == 0. {
-                continue;
+        // Each additional degree has to add at least one independent term, so this is always enough
+        for degree in 0..2 * size {
+            for powers in Self::powers_of_degree(degree, dimensions) {
+                let mut row: Vec<f64> = stencil
+                    .iter()
+                    .map(|offset| Self::term_value(offset, &powers))
+                    .collect();
+
+                for (pivot, basis_row) in &basis {
+                    let factor = row[*pivot] / basis_row[*pivot];
+                    for (r, b) in row.iter_mut().zip(basis_row) {
+                        *r -= factor * b;
+                    }
+                }
+
+                let Some((pivot, _)) = row
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, r)| r.abs() > 1e-9)
+                    .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                else {
+                    continue;
+                };
+
+                basis.push((pivot, row));
+                terms.push(powers);
+
+                if terms.len() == size {
+                    return terms;
+                }
             }
+        }
+
+        terms
+    }
+
+    /// The powers of each index in the terms of a total degree, with higher powers of the earlier indices
+    /// first, e.g. `[2, 0], [1, 1], [0, 2]` for degree 2 in 2D.
+    fn powers_of_degree(degree: usize, dimensions: usize) -> Vec<Vec<usize>> {
+        match dimensions {
+            0 => vec![],
+            1 => vec![vec![degree]],
+            _ => (0..=degree)
+                .rev()
+                .flat_map(|first| {
+                    Self::powers_of_degree(degree - first, dimensions - 1)
+                        .into_iter()
+                        .map(move |mut rest| {
+                            rest.insert(0, first);
+                            rest
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    fn term_value(offset: &[isize], powers: &[usize]) -> f64 {
+        offset
+            .iter()
+            .zip(powers)
+            .map(|(&o, &m)| o.pow(m as u32) as f64 / fact(m) as f64)
+            .product()
+    }
 
-            let stencil_value = self.stencil[i];
+    /// Gives the approximation of the derivative with respect to the given variables, e.g. `[X, Y]`
+    /// for `u_xy`.
+    pub fn get_scheme(&self, variables: &[Variable]) -> Option<MeshExpr> {
+        let dimensions = self.stencil.first()?.len();
+        if variables.iter().any(|v| v.index() >= dimensions) {
+            return None;
+        }
+
+        let powers: Vec<usize> = (0..dimensions)
+            .map(|index| variables.iter().filter(|v| v.index() == index).count())
+            .collect();
+
+        let index = self.terms.iter().position(|t| *t == powers)?;
+        let col = self.cols.get(index)?;
 
-            let (i, j) = match self.variable {
-                Variable::X => (stencil_value, 0),
-                Variable::Y => (0, stencil_value),
-            };
+        let mut terms = Vec::with_capacity(col.len());
+
+        for (&coeff, offset) in col.iter().zip(&self.stencil) {
+            if coeff.abs() < 1e-12 {
+                continue;
+            }
 
             terms.push(MeshExpr::Prod(vec![
                 MeshExpr::Constant(coeff),
-                MeshExpr::AtOffset(i, j),
+                MeshExpr::AtOffset(offset.clone()),
             ]));
         }
 
@@ -76,7 +293,9 @@ impl TaylorTable {
     }
 }
 
-pub type DerivativeApproximations = HashMap<(Variable, usize), MeshExpr>;
+/// Approximations of partial derivatives, keyed by the variables that the derivative is taken with respect to,
+/// in ascending order (e.g. `[X, X, Y]` for `u_xxy`).
+pub type DerivativeApproximations = HashMap<Vec<Variable>, MeshExpr>;
 
 #[cfg(test)]
 mod test {
@@ -85,7 +304,7 @@ mod test {
         taylor::fact,
     };
 
-    use super::TaylorTable;
+    use super::{TaylorTable, TaylorTableND, nonuniform_weights};
 
     #[test]
     fn fact_test() {
@@ -120,9 +339,113 @@ mod test {
         assert_eq!(
             scheme.unwrap(),
             MeshExpr::Sum(vec![
-                MeshExpr::Prod(vec![MeshExpr::Constant(-1.0), MeshExpr::AtOffset(0, 0)]),
-                MeshExpr::Prod(vec![MeshExpr::Constant(1.0), MeshExpr::AtOffset(1, 0)])
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(-1.0),
+                    MeshExpr::AtOffset(vec![0, 0])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(1.0),
+                    MeshExpr::AtOffset(vec![1, 0])
+                ])
             ])
         )
     }
+
+    #[test]
+    fn mixed_derivative_9_nodes() {
+        let mut stencil = Vec::new();
+        for i in -1..=1 {
+            for j in -1..=1 {
+                stencil.push((i, j));
+            }
+        }
+        let table = TaylorTableND::new(stencil.as_slice());
+
+        assert_eq!(table.terms.len(), 9);
+        assert_eq!(
+            table.get_scheme(&[Variable::X, Variable::Y]).unwrap(),
+            MeshExpr::Sum(vec![
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(0.25),
+                    MeshExpr::AtOffset(vec![-1, -1])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(-0.25),
+                    MeshExpr::AtOffset(vec![-1, 1])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(-0.25),
+                    MeshExpr::AtOffset(vec![1, -1])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(0.25),
+                    MeshExpr::AtOffset(vec![1, 1])
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn mixed_derivative_unavailable() {
+        let stencil = vec![(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)];
+        let table = TaylorTableND::new(stencil.as_slice());
+
+        assert!(table.get_scheme(&[Variable::X, Variable::Y]).is_none());
+        assert!(table.get_scheme(&[Variable::Y, Variable::Y]).is_some());
+    }
+
+    #[test]
+    fn three_dimensional_stencil() {
+        // Explicit in time, central differences in space
+        let stencil = vec![
+            (0, 0, 0),
+            (0, 0, -1),
+            (-1, 0, -1),
+            (1, 0, -1),
+            (0, -1, -1),
+            (0, 1, -1),
+        ];
+
+        let time = TaylorTable::new(stencil.as_slice(), Variable::Z);
+        assert_eq!(
+            time.get_scheme(1).unwrap(),
+            MeshExpr::Sum(vec![
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(1.),
+                    MeshExpr::AtOffset(vec![0, 0, 0])
+                ]),
+                MeshExpr::Prod(vec![
+                    MeshExpr::Constant(-1.),
+                    MeshExpr::AtOffset(vec![0, 0, -1])
+                ]),
+            ])
+        );
+
+        // Only the nodes on the axis are used
+        let space = TaylorTable::new(stencil.as_slice(), Variable::X);
+        assert!(space.get_scheme(1).is_none());
+
+        let table = TaylorTableND::new(stencil.as_slice());
+        assert_eq!(table.terms.len(), 6);
+        assert!(table.get_scheme(&[Variable::Y, Variable::Y]).is_some());
+        assert!(table.get_scheme(&[Variable::X, Variable::Y]).is_none());
+    }
+
+    #[test]
+    fn nonuniform_second_derivative() {
+        // With equal spacing, the weights are the usual ones scaled by the spacing
+        let weights = nonuniform_weights(&[-0.5, 0., 0.5], 2).unwrap();
+        for (w, expected) in weights.iter().zip([4., -8., 4.]) {
+            assert!((w - expected).abs() < 1e-12);
+        }
+
+        // Exact for quadratics on any spacing
+        let distances = [-0.1, 0., 0.3];
+        let weights = nonuniform_weights(&distances, 2).unwrap();
+        let approx: f64 = weights.iter().zip(distances).map(|(w, d)| w * d * d).sum();
+        assert!((approx - 2.).abs() < 1e-10);
+
+        assert!(nonuniform_weights(&[0., 1.], 2).is_none());
+        assert!(nonuniform_weights(&[0., 0.], 1).is_none());
+    }
 }
@@ -0,0 +1,729 @@
+/// A problem discretised in space only (the method of lines), which gives a system of ODEs `du/dt = rhs(u)`
+/// for the values of the solution at the nodes of a mesh. The solution is kept in the mesh, so only the
+/// current time level is stored.
+pub trait MethodOfLines {
+    /// The values of the solution at every node.
+    fn state(&self) -> &[f64];
+
+    /// Replaces the values of the solution at `time`, and applies the boundary conditions at that time to
+    /// them.
+    fn set_state(&mut self, time: f64, u: &[f64]);
+
+    /// The time derivative of the solution at every node, given its values `u`.
+    fn rhs(&self, u: &[f64], du: &mut [f64]);
+
+    /// The longest step that is stable for the system, such as the limit from the CFL condition, which
+    /// [`integrate_adaptive`] never exceeds. Defaults to no limit.
+    fn max_dt(&self) -> f64 {
+        f64::INFINITY
+    }
+}
+
+/// Explicit Runge-Kutta schemes for integrating a [`MethodOfLines`] system in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeScheme {
+    /// First order, with a single evaluation of the right hand side per step.
+    ForwardEuler,
+    /// The second order strong stability preserving scheme (Heun's method), which keeps the total variation
+    /// diminishing properties of forward Euler under the same step size limit.
+    SspRk2,
+    /// The third order strong stability preserving scheme of Shu and Osher.
+    SspRk3,
+    /// The classic fourth order Runge-Kutta scheme.
+    Rk4,
+}
+
+impl TimeScheme {
+    /// The order of accuracy of the scheme.
+    pub fn order(&self) -> usize {
+        match self {
+            Self::ForwardEuler => 1,
+            Self::SspRk2 => 2,
+            Self::SspRk3 => 3,
+            Self::Rk4 => 4,
+        }
+    }
+
+    fn tableau(&self) -> Tableau {
+        match self {
+            Self::ForwardEuler => Tableau {
+                a: &[&[]],
+                b: &[1.],
+                b_hat: &[],
+            },
+            Self::SspRk2 => Tableau {
+                a: &[&[], &[1.]],
+                b: &[0.5, 0.5],
+                b_hat: &[],
+            },
+            Self::SspRk3 => Tableau {
+                a: &[&[], &[1.], &[0.25, 0.25]],
+                b: &[1. / 6., 1. / 6., 2. / 3.],
+                b_hat: &[],
+            },
+            Self::Rk4 => Tableau {
+                a: &[&[], &[0.5], &[0., 0.5], &[0., 0., 1.]],
+                b: &[1. / 6., 1. / 3., 1. / 3., 1. / 6.],
+                b_hat: &[],
+            },
+        }
+    }
+}
+
+/// Explicit Runge-Kutta pairs, which give a second solution of lower order from the same stages. The
+/// difference between the two estimates the error of each step, which [`integrate_adaptive`] uses to choose
+/// the step size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbeddedScheme {
+    /// The third order scheme of Bogacki and Shampine, with an embedded second order solution. Cheap per
+    /// step, which suits loose tolerances.
+    BogackiShampine,
+    /// The fifth order scheme of Dormand and Prince, with an embedded fourth order solution.
+    DormandPrince,
+}
+
+impl EmbeddedScheme {
+    /// The order of accuracy of the solution that is kept.
+    pub fn order(&self) -> usize {
+        match self {
+            Self::BogackiShampine => 3,
+            Self::DormandPrince => 5,
+        }
+    }
+
+    /// The order of the embedded solution, which the estimated error scales with.
+    fn embedded_order(&self) -> usize {
+        self.order() - 1
+    }
+
+    fn tableau(&self) -> Tableau {
+        match self {
+            Self::BogackiShampine => Tableau {
+                a: &[&[], &[0.5], &[0., 0.75], &[2. / 9., 1. / 3., 4. / 9.]],
+                b: &[2. / 9., 1. / 3., 4. / 9., 0.],
+                b_hat: &[7. / 24., 0.25, 1. / 3., 0.125],
+            },
+            Self::DormandPrince => Tableau {
+                a: &[
+                    &[],
+                    &[1. / 5.],
+                    &[3. / 40., 9. / 40.],
+                    &[44. / 45., -56. / 15., 32. / 9.],
+                    &[
+                        19372. / 6561.,
+                        -25360. / 2187.,
+                        64448. / 6561.,
+                        -212. / 729.,
+                    ],
+                    &[
+                        9017. / 3168.,
+                        -355. / 33.,
+                        46732. / 5247.,
+                        49. / 176.,
+                        -5103. / 18656.,
+                    ],
+                    &[
+                        35. / 384.,
+                        0.,
+                        500. / 1113.,
+                        125. / 192.,
+                        -2187. / 6784.,
+                        11. / 84.,
+                    ],
+                ],
+                b: &[
+                    35. / 384.,
+                    0.,
+                    500. / 1113.,
+                    125. / 192.,
+                    -2187. / 6784.,
+                    11. / 84.,
+                    0.,
+                ],
+                b_hat: &[
+                    5179. / 57600.,
+                    0.,
+                    7571. / 16695.,
+                    393. / 640.,
+                    -92097. / 339200.,
+                    187. / 2100.,
+                    1. / 40.,
+                ],
+            },
+        }
+    }
+}
+
+/// The Butcher tableau of an explicit Runge-Kutta scheme. The right hand side doesn't depend on time
+/// explicitly, so the nodes `c` are only needed for the time that boundary conditions are applied at in each
+/// stage, and are found as the sums of the rows of `a`.
+struct Tableau {
+    /// The weights of the previous stages in each stage, i.e. the rows of the lower triangle of the matrix.
+    a: &'static [&'static [f64]],
+    /// The weights of the stages in the step.
+    b: &'static [f64],
+    /// The weights of the stages in the embedded solution, which is empty for schemes without one.
+    b_hat: &'static [f64],
+}
+
+/// `u + dt * sum(weights[k] * stages[k])`.
+fn combine(u: &[f64], dt: f64, weights: &[f64], stages: &[Vec<f64>]) -> Vec<f64> {
+    let mut result = u.to_vec();
+
+    for (&weight, stage) in weights.iter().zip(stages) {
+        if weight == 0. {
+            continue;
+        }
+        for (r, k) in result.iter_mut().zip(stage) {
+            *r += dt * weight * k;
+        }
+    }
+
+    result
+}
+
+/// Evaluates the stages of a step of size `dt` from the current state of the system at `time`, giving the
+/// state at the start of the step and the time derivative at each stage. The system is left at the state of
+/// the last stage.
+fn stages<S: MethodOfLines>(
+    system: &mut S,
+    a: &[&[f64]],
+    time: f64,
+    dt: f64,
+) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let start = system.state().to_vec();
+    let mut stages: Vec<Vec<f64>> = Vec::with_capacity(a.len());
+
+    for (k, weights) in a.iter().enumerate() {
+        if k > 0 {
+            let stage = combine(&start, dt, weights, &stages);
+            let c: f64 = weights.iter().sum();
+            system.set_state(time + c * dt, &stage);
+        }
+
+        let mut du = vec![0.; start.len()];
+        system.rhs(system.state(), &mut du);
+        stages.push(du);
+    }
+
+    (start, stages)
+}
+
+/// Advances the system by one step of size `dt` from `time`.
+pub fn step<S: MethodOfLines>(system: &mut S, scheme: TimeScheme, time: f64, dt: f64) {
+    let Tableau { a, b, .. } = scheme.tableau();
+    let (start, stages) = stages(system, a, time, dt);
+
+    system.set_state(time + dt, &combine(&start, dt, b, &stages));
+}
+
+/// The solution at a time that was asked for when integrating.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub time: f64,
+    /// The values at every node, in the order they are stored in the mesh.
+    pub values: Vec<f64>,
+}
+
+/// Integrates the system from `start` to `end` with steps of size `dt`, saving the solution at each of
+/// `snapshot_times` that lies within the interval. Steps are shortened where needed to land exactly on the
+/// snapshot times and on `end`.
+///
+/// # Panics
+/// If `dt` isn't positive or `end` is before `start`.
+pub fn integrate<S: MethodOfLines>(
+    system: &mut S,
+    scheme: TimeScheme,
+    start: f64,
+    end: f64,
+    dt: f64,
+    snapshot_times: &[f64],
+) -> Vec<Snapshot> {
+    assert!(dt > 0., "The time step should be positive.");
+    assert!(end >= start, "The end time should be after the start time.");
+
+    // Steps much shorter than `dt` are merged into the previous step rather than taken on their own
+    let tolerance = 1e-9 * dt;
+
+    let mut stops: Vec<f64> = snapshot_times
+        .iter()
+        .copied()
+        .filter(|t| (start..=end).contains(t))
+        .collect();
+    stops.sort_by(f64::total_cmp);
+
+    let mut snapshots = Vec::with_capacity(stops.len());
+    let mut stops = stops.into_iter().peekable();
+    let mut time = start;
+
+    loop {
+        while let Some(stop) = stops.next_if(|&stop| stop <= time + tolerance) {
+            snapshots.push(Snapshot {
+                time: stop,
+                values: system.state().to_vec(),
+            });
+        }
+
+        if time >= end - tolerance {
+            break;
+        }
+
+        let target = stops.peek().copied().unwrap_or(end);
+        let dt = if target - time <= dt + tolerance {
+            target - time
+        } else {
+            dt
+        };
+
+        step(system, scheme, time, dt);
+        time = if target - time - dt <= tolerance {
+            target
+        } else {
+            time + dt
+        };
+    }
+
+    snapshots
+}
+
+/// Settings for [`integrate_adaptive`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepControl {
+    pub scheme: EmbeddedScheme,
+    /// The error allowed in a step at each node is `absolute_tolerance + relative_tolerance * |u|`.
+    pub relative_tolerance: f64,
+    pub absolute_tolerance: f64,
+    /// The size of the first step that is tried.
+    pub initial_dt: f64,
+    /// The longest step allowed, on top of the limit from [`MethodOfLines::max_dt`].
+    pub max_dt: f64,
+}
+
+impl StepControl {
+    /// Settings with the same relative and absolute tolerance, and no limit on the step size besides that of
+    /// the system.
+    pub fn new(scheme: EmbeddedScheme, tolerance: f64, initial_dt: f64) -> Self {
+        Self {
+            scheme,
+            relative_tolerance: tolerance,
+            absolute_tolerance: tolerance,
+            initial_dt,
+            max_dt: f64::INFINITY,
+        }
+    }
+
+    /// The factor to scale the step size by, given the error of a step relative to the tolerance. The step
+    /// is aimed at an error just below the tolerance, and the change is limited to keep the steps smooth.
+    fn step_factor(&self, error: f64) -> f64 {
+        const SAFETY: f64 = 0.9;
+        const MIN_FACTOR: f64 = 0.2;
+        const MAX_FACTOR: f64 = 5.;
+
+        if error == 0. {
+            return MAX_FACTOR;
+        }
+
+        let exponent = -1. / (self.scheme.embedded_order() + 1) as f64;
+        (SAFETY * error.powf(exponent)).clamp(MIN_FACTOR, MAX_FACTOR)
+    }
+}
+
+/// How an adaptive integration went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveReport {
+    /// The solution at each of the snapshot times, in order of time.
+    pub snapshots: Vec<Snapshot>,
+    /// The size of each accepted step, in order.
+    pub dt_history: Vec<f64>,
+    /// The number of steps that were rejected because their error was above the tolerance, and retried with
+    /// a smaller step.
+    pub rejected_steps: usize,
+}
+
+impl AdaptiveReport {
+    /// The number of steps that were accepted.
+    pub fn accepted_steps(&self) -> usize {
+        self.dt_history.len()
+    }
+
+    /// The size of the last accepted step, or `None` if no steps were taken.
+    pub fn final_dt(&self) -> Option<f64> {
+        self.dt_history.last().copied()
+    }
+}
+
+/// Integrates the system from `start` to `end` with an embedded Runge-Kutta pair, choosing the size of each
+/// step so that its estimated error is within the tolerance, saving the solution at each of `snapshot_times`
+/// that lies within the interval. Steps are never longer than [`MethodOfLines::max_dt`] or
+/// [`StepControl::max_dt`], and are shortened where needed to land exactly on the snapshot times and on
+/// `end`.
+///
+/// # Panics
+/// If the initial step isn't positive, `end` is before `start`, or the step needed to meet the tolerance
+/// becomes too small to advance the time.
+pub fn integrate_adaptive<S: MethodOfLines>(
+    system: &mut S,
+    control: StepControl,
+    start: f64,
+    end: f64,
+    snapshot_times: &[f64],
+) -> AdaptiveReport {
+    assert!(
+        control.initial_dt > 0.,
+        "The initial time step should be positive."
+    );
+    assert!(end >= start, "The end time should be after the start time.");
+
+    let Tableau { a, b, b_hat } = control.scheme.tableau();
+
+    let mut stops: Vec<f64> = snapshot_times
+        .iter()
+        .copied()
+        .filter(|t| (start..=end).contains(t))
+        .collect();
+    stops.sort_by(f64::total_cmp);
+
+    let mut report = AdaptiveReport {
+        snapshots: Vec::with_capacity(stops.len()),
+        dt_history: Vec::new(),
+        rejected_steps: 0,
+    };
+    let mut stops = stops.into_iter().peekable();
+    let mut time = start;
+    // The step the error control asks for, before it is limited by the snapshot times
+    let mut proposed = control.initial_dt;
+
+    loop {
+        let tolerance = 1e-9 * proposed;
+
+        while let Some(stop) = stops.next_if(|&stop| stop <= time + tolerance) {
+            report.snapshots.push(Snapshot {
+                time: stop,
+                values: system.state().to_vec(),
+            });
+        }
+
+        if time >= end - tolerance {
+            break;
+        }
+
+        let target = stops.peek().copied().unwrap_or(end);
+        let limit = proposed.min(control.max_dt).min(system.max_dt());
+        let (dt, reaches_target) = if target - time <= limit + tolerance {
+            (target - time, true)
+        } else {
+            (limit, false)
+        };
+        assert!(
+            time + dt > time,
+            "The time step needed to meet the tolerance is too small to advance from t = {time}."
+        );
+
+        let (start_state, stages) = stages(system, a, time, dt);
+        let solution = combine(&start_state, dt, b, &stages);
+        let embedded = combine(&start_state, dt, b_hat, &stages);
+
+        let error = start_state
+            .iter()
+            .zip(&solution)
+            .zip(&embedded)
+            .map(|((u0, u1), e)| {
+                let scale = control.absolute_tolerance
+                    + control.relative_tolerance * u0.abs().max(u1.abs());
+                (u1 - e).abs() / scale
+            })
+            // A step that overflowed gives NaN, which `f64::max` would drop, so it counts as too large
+            .fold(0., |max: f64, e| {
+                if e.is_nan() {
+                    f64::INFINITY
+                } else {
+                    max.max(e)
+                }
+            });
+        let factor = control.step_factor(error);
+
+        if error > 1. {
+            system.set_state(time, &start_state);
+            report.rejected_steps += 1;
+            proposed = dt * factor;
+            continue;
+        }
+
+        time = if reaches_target { target } else { time + dt };
+        system.set_state(time, &solution);
+        report.dt_history.push(dt);
+
+        // A step shortened to land on a target says little about how long the next one can be
+        proposed = if dt < proposed {
+            proposed.max(dt * factor)
+        } else {
+            dt * factor
+        };
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        EmbeddedScheme, MethodOfLines, StepControl, TimeScheme, combine, integrate,
+        integrate_adaptive, stages,
+    };
+
+    /// `du/dt = -u`, with the first value held fixed as a boundary condition.
+    struct Decay {
+        u: Vec<f64>,
+    }
+
+    /// `du/dt = -u^2` from `u = 1`, whose solution is `1 / (1 + t)`, with an optional limit on the step size.
+    /// Embedded pairs need a nonlinear problem, as their two solutions can agree on linear ones.
+    struct Quadratic {
+        u: Vec<f64>,
+        max_dt: f64,
+    }
+
+    impl Quadratic {
+        fn new() -> Self {
+            Self {
+                u: vec![1.],
+                max_dt: f64::INFINITY,
+            }
+        }
+    }
+
+    impl MethodOfLines for Quadratic {
+        fn state(&self) -> &[f64] {
+            &self.u
+        }
+
+        fn set_state(&mut self, _time: f64, u: &[f64]) {
+            self.u.copy_from_slice(u);
+        }
+
+        fn rhs(&self, u: &[f64], du: &mut [f64]) {
+            for (d, u) in du.iter_mut().zip(u) {
+                *d = -u * u;
+            }
+        }
+
+        fn max_dt(&self) -> f64 {
+            self.max_dt
+        }
+    }
+
+    impl MethodOfLines for Decay {
+        fn state(&self) -> &[f64] {
+            &self.u
+        }
+
+        fn set_state(&mut self, _time: f64, u: &[f64]) {
+            self.u.copy_from_slice(u);
+            self.u[0] = 1.;
+        }
+
+        fn rhs(&self, u: &[f64], du: &mut [f64]) {
+            for (d, u) in du.iter_mut().zip(u) {
+                *d = -u;
+            }
+        }
+    }
+
+    /// `du/dt = g(t)`, with `g` held in the first value as a boundary condition that varies in time, so the
+    /// solution is the integral of `g`.
+    struct Ramp {
+        u: Vec<f64>,
+    }
+
+    impl MethodOfLines for Ramp {
+        fn state(&self) -> &[f64] {
+            &self.u
+        }
+
+        fn set_state(&mut self, time: f64, u: &[f64]) {
+            self.u.copy_from_slice(u);
+            self.u[0] = time * time;
+        }
+
+        fn rhs(&self, u: &[f64], du: &mut [f64]) {
+            du[0] = 0.;
+            du[1] = u[0];
+        }
+    }
+
+    /// `du/dt = -sqrt(u)` from `u = 1`, whose solution is `(1 - t / 2)^2` until it reaches zero at `t = 2`.
+    /// Steps that overshoot below zero give NaN.
+    struct Root {
+        u: Vec<f64>,
+    }
+
+    impl MethodOfLines for Root {
+        fn state(&self) -> &[f64] {
+            &self.u
+        }
+
+        fn set_state(&mut self, _time: f64, u: &[f64]) {
+            self.u.copy_from_slice(u);
+        }
+
+        fn rhs(&self, u: &[f64], du: &mut [f64]) {
+            du[0] = -u[0].sqrt();
+        }
+    }
+
+    fn error(scheme: TimeScheme, dt: f64) -> f64 {
+        let mut system = Decay { u: vec![1., 1.] };
+        integrate(&mut system, scheme, 0., 1., dt, &[]);
+
+        assert_eq!(system.u[0], 1.);
+        (system.u[1] - (-1f64).exp()).abs()
+    }
+
+    #[test]
+    fn orders() {
+        for scheme in [
+            TimeScheme::ForwardEuler,
+            TimeScheme::SspRk2,
+            TimeScheme::SspRk3,
+            TimeScheme::Rk4,
+        ] {
+            let ratio = error(scheme, 0.02) / error(scheme, 0.01);
+            let order = ratio.log2();
+
+            assert!(
+                (order - scheme.order() as f64).abs() < 0.1,
+                "{scheme:?} has order {order}"
+            );
+        }
+    }
+
+    #[test]
+    fn time_dependent_boundary() {
+        // Each stage sees the boundary value at its own time, so schemes of third order and above integrate
+        // t^2 exactly
+        for scheme in [TimeScheme::SspRk3, TimeScheme::Rk4] {
+            let mut system = Ramp { u: vec![0., 0.] };
+            integrate(&mut system, scheme, 0., 1., 0.1, &[]);
+
+            assert!((system.u[1] - 1. / 3.).abs() < 1e-12, "{scheme:?}");
+            assert_eq!(system.u[0], 1.);
+        }
+
+        let mut system = Ramp { u: vec![0., 0.] };
+        let control = StepControl::new(EmbeddedScheme::DormandPrince, 1e-8, 0.1);
+        integrate_adaptive(&mut system, control, 0., 1., &[]);
+        assert!((system.u[1] - 1. / 3.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut system = Decay { u: vec![1., 1.] };
+        let snapshots = integrate(
+            &mut system,
+            TimeScheme::Rk4,
+            0.,
+            1.,
+            0.3,
+            &[0.5, 0., 2., 0.25],
+        );
+
+        let times: Vec<_> = snapshots.iter().map(|s| s.time).collect();
+        assert_eq!(times, [0., 0.25, 0.5]);
+        assert_eq!(snapshots[0].values, [1., 1.]);
+        assert!((snapshots[2].values[1] - (-0.5f64).exp()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn embedded_orders() {
+        for scheme in [
+            EmbeddedScheme::BogackiShampine,
+            EmbeddedScheme::DormandPrince,
+        ] {
+            let tableau = scheme.tableau();
+
+            // Both solutions of a pair, with fixed steps
+            let errors = |dt: f64| {
+                let mut system = Quadratic::new();
+                let mut embedded = Quadratic::new();
+                for n in 0..(1. / dt).round() as usize {
+                    let time = n as f64 * dt;
+
+                    let (start, k) = stages(&mut system, tableau.a, time, dt);
+                    system.set_state(time + dt, &combine(&start, dt, tableau.b, &k));
+
+                    let (start, k) = stages(&mut embedded, tableau.a, time, dt);
+                    embedded.set_state(time + dt, &combine(&start, dt, tableau.b_hat, &k));
+                }
+
+                ((system.u[0] - 0.5).abs(), (embedded.u[0] - 0.5).abs())
+            };
+
+            let (coarse, coarse_embedded) = errors(0.025);
+            let (fine, fine_embedded) = errors(0.0125);
+            let order = (coarse / fine).log2();
+            let embedded_order = (coarse_embedded / fine_embedded).log2();
+
+            assert!(
+                (order - scheme.order() as f64).abs() < 0.4,
+                "{scheme:?} has order {order}"
+            );
+            assert!(
+                (embedded_order - scheme.embedded_order() as f64).abs() < 0.4,
+                "{scheme:?} has embedded order {embedded_order}"
+            );
+        }
+    }
+
+    #[test]
+    fn adaptive() {
+        for scheme in [
+            EmbeddedScheme::BogackiShampine,
+            EmbeddedScheme::DormandPrince,
+        ] {
+            let mut system = Quadratic::new();
+            // A first step far too long for the tolerance, which has to be rejected
+            let control = StepControl::new(scheme, 1e-8, 1.);
+            let report = integrate_adaptive(&mut system, control, 0., 2., &[1.]);
+
+            assert!(report.rejected_steps > 0);
+            assert!((report.dt_history.iter().sum::<f64>() - 2.).abs() < 1e-12);
+            assert_eq!(report.snapshots.len(), 1);
+            assert!((report.snapshots[0].values[0] - 0.5).abs() < 1e-6);
+            assert!((system.u[0] - 1. / 3.).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn nan_rejected() {
+        let mut system = Root { u: vec![1.] };
+        // The first step covers the whole interval, and its stages overshoot past zero and take the root of a
+        // negative number
+        let control = StepControl::new(EmbeddedScheme::DormandPrince, 1e-8, 10.);
+        let report = integrate_adaptive(&mut system, control, 0., 1.5, &[]);
+
+        assert!(report.rejected_steps > 0);
+        assert!((system.u[0] - 0.0625).abs() < 1e-6, "u = {}", system.u[0]);
+    }
+
+    #[test]
+    fn step_limits() {
+        let mut system = Quadratic {
+            max_dt: 0.01,
+            ..Quadratic::new()
+        };
+        let control = StepControl::new(EmbeddedScheme::DormandPrince, 1e-3, 0.1);
+        let report = integrate_adaptive(&mut system, control, 0., 0.5, &[0.123]);
+
+        assert_eq!(report.rejected_steps, 0);
+        assert!(report.dt_history.iter().all(|&dt| dt <= 0.01 + 1e-15));
+        assert_eq!(report.snapshots[0].time, 0.123);
+        assert!(report.accepted_steps() >= 50);
+
+        let control = StepControl {
+            max_dt: 0.001,
+            ..control
+        };
+        let report = integrate_adaptive(&mut system, control, 0.5, 0.6, &[]);
+        assert!((report.final_dt().unwrap() - 0.001).abs() < 1e-12);
+    }
+}
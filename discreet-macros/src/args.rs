@@ -1,11 +1,16 @@
+use discreet_common::algebra::{Expression, Variable};
+use proc_macro2::{Group, Span};
+use quote::ToTokens;
 use syn::{
-    Expr, ExprLit, ExprPath, Ident, Lit, Path, Token, UnOp,
-    parse::{Parse, ParseStream},
+    Expr, ExprAssign, ExprCall, ExprLit, ExprPath, Ident, Lit, Path, Token, UnOp, braced,
+    parse::{Parse, ParseStream, Parser},
     punctuated::Punctuated,
     spanned::Spanned,
-    token::Minus,
+    token::{Brace, Minus},
 };
 
+use crate::diff_eq::parse_pde;
+
 pub struct CommaSeparatedArgs {
     items: Vec<Arg>,
 }
@@ -52,7 +57,15 @@ impl Parse for Arg {
 
         let value: Expr = if next.peek(Token![:]) {
             let _: Token![:] = input.parse()?;
-            input.parse()?
+
+            // Blocks of named items like `boundaries: { left: ... }` aren't expressions, so they are kept as
+            // tokens to be parsed by the argument that uses them.
+            if input.peek(Brace) {
+                let group: Group = input.parse()?;
+                Expr::Verbatim(group.into_token_stream())
+            } else {
+                input.parse()?
+            }
         } else if next.peek(Token![,]) {
             syn::parse_str("true")?
         } else {
@@ -63,29 +76,135 @@ impl Parse for Arg {
     }
 }
 
-pub fn parse_stencil(stencil: Expr) -> syn::Result<Vec<(isize, isize)>> {
-    let span = stencil.span();
-    match stencil {
-        Expr::Array(a) => {
-            let elems = a.elems;
+/// The error for giving both `time` and `unknown`, as a scheme integrated in time has no unknown.
+const TIME_AND_UNKNOWN: &str =
+    "With `time`, every node is advanced in time together, so the scheme has no `unknown`.";
 
-            let mut stencil = Vec::with_capacity(elems.len());
-            for item in elems {
-                match item {
-                    Expr::Tuple(e) => {
-                        let mut iter = e.elems.into_iter();
+/// The arguments shared by the macros for meshes of every number of dimensions, parsed and checked against
+/// each other. Checks that depend on the number of dimensions are left to each macro.
+pub struct SchemeArgs {
+    pub dims: Dimensions,
+    /// With `time`, the dimensions followed by time, and the variable that time is.
+    pub time: Option<(Dimensions, Variable)>,
+    pub max_dt: Option<Expr>,
+    pub equation: Expression,
+    pub equation_span: Span,
+    pub stencil: Vec<Vec<isize>>,
+    pub stencil_span: Span,
+    /// The nodes solved for, which default to the center of the stencil.
+    pub unknowns: Vec<Vec<isize>>,
+    pub unknowns_span: Span,
+    pub constants: Vec<(Ident, Option<Expr>)>,
+    pub functions: Vec<Ident>,
+    pub boundaries: Vec<(Ident, BoundaryDecl)>,
+}
 
-                        let x = parse_int_lit(iter.next().unwrap())?;
-                        let y = parse_int_lit(iter.next().unwrap())?;
+impl SchemeArgs {
+    /// Parses the arguments of a macro for a mesh with `num_dimensions` indices, whose boundaries are called
+    /// `boundary_names`. The equation and the stencil have to be given, and the rest are optional.
+    pub fn parse(
+        parsed: &CommaSeparatedArgs,
+        num_dimensions: usize,
+        boundary_names: &[&str],
+    ) -> syn::Result<Self> {
+        let dims = match parsed.find_arg("dimensions".to_string()) {
+            Some(dims) => parse_dimensions(dims, num_dimensions)?,
+            None => Dimensions::with_default_names(num_dimensions),
+        };
+        let time = parsed
+            .find_arg("time".to_string())
+            .map(|time| dims.with_time(time))
+            .transpose()?;
+        let max_dt = match parsed.find_arg("max_dt".to_string()) {
+            Some(limit) if time.is_none() => {
+                return Err(syn::Error::new(
+                    limit.span(),
+                    "`max_dt` can only be used with `time`.",
+                ));
+            }
+            limit => limit,
+        };
 
-                        stencil.push((x, y));
-                    }
-                    _ => return Err(syn::Error::new(span, "Expected tuple.")),
+        let Some(equation) = parsed.find_arg("equation".to_string()) else {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "Expected an `equation` argument.",
+            ));
+        };
+        let equation_span = equation.span();
+        let equation = parse_pde(equation, time.as_ref().map_or(&dims, |(d, _)| d))?;
+
+        let Some(stencil) = parsed.find_arg("stencil".to_string()) else {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "Expected a `stencil` argument.",
+            ));
+        };
+        let stencil_span = stencil.span();
+        let stencil = parse_offsets(stencil, num_dimensions)?;
+
+        let (unknowns, unknowns_span) = match parsed.find_arg("unknown".to_string()) {
+            Some(unknowns) if time.is_some() => {
+                return Err(syn::Error::new(unknowns.span(), TIME_AND_UNKNOWN));
+            }
+            Some(unknowns) => {
+                let span = unknowns.span();
+                let unknowns = match unknowns {
+                    Expr::Array(_) => parse_offsets(unknowns, num_dimensions)?,
+                    other => vec![parse_offset(other, num_dimensions)?],
+                };
+
+                if unknowns.is_empty() || unknowns.iter().any(|u| !stencil.contains(u)) {
+                    return Err(syn::Error::new(
+                        span,
+                        "The unknowns should be nodes of the stencil.",
+                    ));
                 }
+                (unknowns, span)
             }
+            None => (vec![vec![0; num_dimensions]], Span::call_site()),
+        };
 
-            Ok(stencil)
-        }
+        let constants = match parsed.find_arg("constants".to_string()) {
+            Some(constants) => constant_list(constants)?,
+            None => vec![],
+        };
+        let functions = match parsed.find_arg("functions".to_string()) {
+            Some(functions) => ident_list(functions)?,
+            None => vec![],
+        };
+        let boundaries = match parsed.find_arg("boundaries".to_string()) {
+            Some(boundaries) => parse_boundaries(boundaries, boundary_names)?,
+            None => vec![],
+        };
+
+        Ok(Self {
+            dims,
+            time,
+            max_dt,
+            equation,
+            equation_span,
+            stencil,
+            stencil_span,
+            unknowns,
+            unknowns_span,
+            constants,
+            functions,
+            boundaries,
+        })
+    }
+}
+
+/// Parses an array of offsets with `num_dimensions` components each, such as the stencil of a
+/// `finite_diff_3d!` scheme.
+pub fn parse_offsets(stencil: Expr, num_dimensions: usize) -> syn::Result<Vec<Vec<isize>>> {
+    let span = stencil.span();
+    match stencil {
+        Expr::Array(a) => a
+            .elems
+            .into_iter()
+            .map(|item| parse_offset(item, num_dimensions))
+            .collect(),
         _ => Err(syn::Error::new(
             span,
             "Expected stencil to be an array of offsets.",
@@ -93,6 +212,26 @@ pub fn parse_stencil(stencil: Expr) -> syn::Result<Vec<(isize, isize)>> {
     }
 }
 
+/// Parses a single offset, e.g. `(0, -1)`, which should have `num_dimensions` components. Offsets on a 1D
+/// mesh are given as integers, e.g. `-1`.
+pub fn parse_offset(offset: Expr, num_dimensions: usize) -> syn::Result<Vec<isize>> {
+    let span = offset.span();
+    let e = match offset {
+        Expr::Tuple(e) => e,
+        other if num_dimensions == 1 => return Ok(vec![parse_int_lit(other)?]),
+        _ => return Err(syn::Error::new(span, "Expected tuple.")),
+    };
+
+    if e.elems.len() != num_dimensions {
+        return Err(syn::Error::new(
+            span,
+            format!("Expected offsets with {num_dimensions} components."),
+        ));
+    }
+
+    e.elems.into_iter().map(parse_int_lit).collect()
+}
+
 pub fn parse_int_lit(e: Expr) -> syn::Result<isize> {
     match e {
         Expr::Lit(ExprLit {
@@ -145,6 +284,61 @@ pub fn ident_list(expr: Expr) -> syn::Result<Vec<Ident>> {
     }
 }
 
+/// Parses a list of constants, each of which can optionally have a default value (e.g. `[c, nu = 0.1]`).
+/// Integer default values are converted to floating point literals.
+pub fn constant_list(expr: Expr) -> syn::Result<Vec<(Ident, Option<Expr>)>> {
+    let span = expr.span();
+    match expr {
+        Expr::Array(a) => {
+            let elems = a.elems;
+
+            let mut constants: Vec<(Ident, Option<Expr>)> = Vec::with_capacity(elems.len());
+            for item in elems {
+                let constant = match item {
+                    Expr::Assign(ExprAssign { left, right, .. }) => {
+                        (get_ident(*left)?, Some(float_literal(*right)?))
+                    }
+                    other => (get_ident(other)?, None),
+                };
+
+                if constants.iter().any(|(c, _)| *c == constant.0) {
+                    return Err(syn::Error::new(constant.0.span(), "Duplicate constant."));
+                }
+                constants.push(constant);
+            }
+
+            Ok(constants)
+        }
+        _ => Err(syn::Error::new(
+            span,
+            "Expected `constants` to be an array of identifiers, optionally with default values.",
+        )),
+    }
+}
+
+/// Converts integer literals to floating point literals, so that values like `0` can be used where an `f64`
+/// is expected. Negated and parenthesised literals like `-1` are converted too. Other expressions are left as
+/// they are.
+fn float_literal(expr: Expr) -> syn::Result<Expr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(i), ..
+        }) => {
+            let value: f64 = i.base10_parse()?;
+            Ok(syn::parse_quote!(#value))
+        }
+        Expr::Unary(mut e) => {
+            *e.expr = float_literal(*e.expr)?;
+            Ok(Expr::Unary(e))
+        }
+        Expr::Paren(mut e) => {
+            *e.expr = float_literal(*e.expr)?;
+            Ok(Expr::Paren(e))
+        }
+        other => Ok(other),
+    }
+}
+
 pub fn get_ident(expr: Expr) -> syn::Result<Ident> {
     let span = expr.span();
     match expr {
@@ -155,7 +349,7 @@ pub fn get_ident(expr: Expr) -> syn::Result<Ident> {
                     "Expected `constants` and `functions` to be an array of identifiers without a path.",
                 ));
             }
-            return Ok(path.segments.first().unwrap().ident.clone());
+            Ok(path.segments.first().unwrap().ident.clone())
         }
         _ => Err(syn::Error::new(
             span,
@@ -163,3 +357,369 @@ pub fn get_ident(expr: Expr) -> syn::Result<Ident> {
         )),
     }
 }
+
+/// The names of the independent variables, in the order of the mesh indices they correspond to.
+pub struct Dimensions {
+    names: Vec<char>,
+}
+
+impl Dimensions {
+    /// The default names for a mesh with `num_dimensions` indices: `x`, `y` and `z`.
+    pub fn with_default_names(num_dimensions: usize) -> Self {
+        Self {
+            names: ['x', 'y', 'z'][..num_dimensions].to_vec(),
+        }
+    }
+
+    pub fn variable(&self, name: char) -> Option<Variable> {
+        let index = self.names.iter().position(|&n| n == name)?;
+        Variable::from_index(index)
+    }
+
+    /// The names as identifiers, e.g. for the parameters of a closure of the coordinates.
+    pub fn idents(&self) -> Vec<Ident> {
+        self.names
+            .iter()
+            .map(|n| Ident::new(&n.to_string(), Span::call_site()))
+            .collect()
+    }
+
+    /// The dimensions followed by time, named by `time`, for parsing an equation that is integrated in time
+    /// with the method of lines. Gives the variable that derivatives w.r.t. time are taken with respect to.
+    pub fn with_time(&self, time: Expr) -> syn::Result<(Self, Variable)> {
+        let span = time.span();
+        let name = dimension_name(time, &self.names)?;
+
+        let mut names = self.names.clone();
+        names.push(name);
+        let variable = Variable::from_index(self.names.len()).ok_or_else(|| {
+            syn::Error::new(
+                span,
+                "`time` can only be used with meshes of at most 2 dimensions.",
+            )
+        })?;
+
+        Ok((Self { names }, variable))
+    }
+
+    /// The name of the derivative of `u` w.r.t. `vars` in subscript notation, e.g. `u_xy`.
+    pub fn derivative_name(&self, vars: &[Variable]) -> String {
+        let names: String = vars.iter().map(|v| self.names[v.index()]).collect();
+        format!("u_{names}")
+    }
+
+    pub fn names(&self) -> String {
+        let names: Vec<_> = self.names.iter().map(char::to_string).collect();
+        names.join(", ")
+    }
+}
+
+impl Default for Dimensions {
+    fn default() -> Self {
+        Self::with_default_names(2)
+    }
+}
+
+pub fn parse_dimensions(expr: Expr, num_dimensions: usize) -> syn::Result<Dimensions> {
+    let span = expr.span();
+    let elems: Vec<Expr> = match expr {
+        Expr::Tuple(t) => t.elems.into_iter().collect(),
+        Expr::Paren(p) => vec![*p.expr],
+        _ => {
+            return Err(syn::Error::new(
+                span,
+                "Expected `dimensions` to be a tuple of identifiers, e.g. `(x, t)`.",
+            ));
+        }
+    };
+
+    if elems.len() != num_dimensions {
+        return Err(syn::Error::new(
+            span,
+            format!(
+                "Expected {num_dimensions} dimensions, found {}.",
+                elems.len()
+            ),
+        ));
+    }
+
+    let mut names = Vec::with_capacity(elems.len());
+    for elem in elems {
+        let name = dimension_name(elem, &names)?;
+        names.push(name);
+    }
+
+    Ok(Dimensions { names })
+}
+
+/// Parses the name of a dimension, which should be a single character that isn't `u` or one of the `names`
+/// already given.
+fn dimension_name(expr: Expr, names: &[char]) -> syn::Result<char> {
+    let id = get_ident(expr)?;
+    let string = id.to_string();
+
+    let mut chars = string.chars();
+    let (Some(name), None) = (chars.next(), chars.next()) else {
+        return Err(syn::Error::new(
+            id.span(),
+            "Dimensions should be named by a single character, so they can be used in derivatives like `u_x`.",
+        ));
+    };
+
+    if name == 'u' {
+        return Err(syn::Error::new(
+            id.span(),
+            "`u` is reserved for the solution and can't be used as a dimension.",
+        ));
+    }
+    if names.contains(&name) {
+        return Err(syn::Error::new(id.span(), "Duplicate dimension."));
+    }
+
+    Ok(name)
+}
+
+/// A boundary condition declared in the macro. The values are Rust expressions, which can use the constants.
+/// Dirichlet values can also use the coordinates, named as in `dimensions`.
+pub enum BoundaryDecl {
+    Dirichlet(Expr),
+    Neumann(Expr),
+    /// The values of `a`, `b` and the right hand side.
+    Robin(Box<[Expr; 3]>),
+}
+
+/// The ends of a 1D mesh, named as in the macro arguments.
+pub const BOUNDARIES_1D: &[&str] = &["left", "right"];
+
+/// The boundaries of a 2D mesh, named as in the macro arguments.
+pub const BOUNDARIES_2D: &[&str] = &["left", "right", "top", "bottom"];
+
+/// The faces of a 3D mesh, named as in the macro arguments.
+pub const FACES_3D: &[&str] = &["left", "right", "bottom", "top", "back", "front"];
+
+/// Parses the conditions on each boundary, e.g. `{ left: dirichlet(0.0), right: neumann(0.0) }`, where
+/// `names` are the boundaries that the mesh has. The boundaries are given by the name of the corresponding
+/// `Boundary` (or `Face`) variant.
+pub fn parse_boundaries(expr: Expr, names: &[&str]) -> syn::Result<Vec<(Ident, BoundaryDecl)>> {
+    let span = expr.span();
+    let Expr::Verbatim(tokens) = expr else {
+        return Err(syn::Error::new(
+            span,
+            "Expected `boundaries` to be a block of conditions, e.g. `{ left: dirichlet(0.0) }`.",
+        ));
+    };
+
+    let parser = |input: ParseStream| {
+        let content;
+        braced!(content in input);
+        Punctuated::<Arg, Token![,]>::parse_terminated(&content)
+    };
+    let items = parser.parse2(tokens)?;
+
+    let mut boundaries: Vec<(Ident, BoundaryDecl)> = Vec::with_capacity(items.len());
+    for Arg { ident, value } in items {
+        let name = ident.to_string();
+        if !names.contains(&name.as_str()) {
+            let expected: Vec<_> = names.iter().map(|n| format!("`{n}`")).collect();
+            return Err(syn::Error::new(
+                ident.span(),
+                format!("Expected a boundary: one of {}.", expected.join(", ")),
+            ));
+        }
+
+        // The variant of the same name
+        let mut chars = name.chars();
+        let variant: String = chars
+            .next()
+            .into_iter()
+            .flat_map(char::to_uppercase)
+            .chain(chars)
+            .collect();
+        let boundary = Ident::new(&variant, ident.span());
+
+        if boundaries.iter().any(|(b, _)| *b == boundary) {
+            return Err(syn::Error::new(ident.span(), "Duplicate boundary."));
+        }
+
+        boundaries.push((boundary, parse_boundary_condition(value)?));
+    }
+
+    Ok(boundaries)
+}
+
+fn parse_boundary_condition(expr: Expr) -> syn::Result<BoundaryDecl> {
+    let span = expr.span();
+    let error = || {
+        syn::Error::new(
+            span,
+            "Expected a boundary condition: `dirichlet(value)`, `neumann(value)` or `robin(a, b, value)`.",
+        )
+    };
+
+    let Expr::Call(ExprCall { func, args, .. }) = expr else {
+        return Err(error());
+    };
+    let kind = get_ident(*func).map_err(|_| error())?;
+    let args = args
+        .into_iter()
+        .map(float_literal)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    match (kind.to_string().as_str(), args.as_slice()) {
+        ("dirichlet", [value]) => Ok(BoundaryDecl::Dirichlet(value.clone())),
+        ("neumann", [value]) => Ok(BoundaryDecl::Neumann(value.clone())),
+        ("robin", [a, b, value]) => Ok(BoundaryDecl::Robin(Box::new([
+            a.clone(),
+            b.clone(),
+            value.clone(),
+        ]))),
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quote::quote;
+    use syn::Expr;
+
+    use super::{
+        BOUNDARIES_1D, BOUNDARIES_2D, BoundaryDecl, CommaSeparatedArgs, FACES_3D, SchemeArgs,
+        constant_list, parse_boundaries, parse_offsets,
+    };
+
+    fn boundaries_arg(tokens: proc_macro2::TokenStream) -> Expr {
+        let args: CommaSeparatedArgs = syn::parse2(tokens).unwrap();
+        args.find_arg("boundaries".to_string()).unwrap()
+    }
+
+    #[test]
+    fn boundaries() {
+        let arg = boundaries_arg(quote! {
+            equation: u_xx = 0,
+            boundaries: { left: dirichlet(x * t), bottom: neumann(0), top: robin(1, k, 2.5) },
+        });
+
+        let boundaries = parse_boundaries(arg, BOUNDARIES_2D).unwrap();
+        let names: Vec<_> = boundaries.iter().map(|(b, _)| b.to_string()).collect();
+        assert_eq!(names, ["Left", "Bottom", "Top"]);
+
+        assert!(matches!(boundaries[0].1, BoundaryDecl::Dirichlet(_)));
+        let BoundaryDecl::Neumann(value) = &boundaries[1].1 else {
+            panic!("Expected a Neumann condition");
+        };
+        // Integer values are converted to floats
+        assert_eq!(quote!(#value).to_string(), "0f64");
+        assert!(matches!(boundaries[2].1, BoundaryDecl::Robin(_)));
+    }
+
+    #[test]
+    fn negative_defaults() {
+        let constants = constant_list(syn::parse_quote!([c = -1, k, d = (-2)])).unwrap();
+        let defaults: Vec<_> = constants
+            .iter()
+            .map(|(_, default)| default.as_ref().map(|d| quote!(#d).to_string()))
+            .collect();
+
+        assert_eq!(
+            defaults,
+            [
+                Some("- 1f64".to_string()),
+                None,
+                Some("(- 2f64)".to_string())
+            ]
+        );
+
+        let arg = boundaries_arg(quote!(boundaries: { left: dirichlet(-1) }));
+        let boundaries = parse_boundaries(arg, BOUNDARIES_2D).unwrap();
+        let BoundaryDecl::Dirichlet(value) = &boundaries[0].1 else {
+            panic!("Expected a Dirichlet condition");
+        };
+        assert_eq!(quote!(#value).to_string(), "- 1f64");
+    }
+
+    #[test]
+    fn invalid_boundaries() {
+        for tokens in [
+            quote!(boundaries: { middle: dirichlet(0.0) }),
+            quote!(boundaries: { left: dirichlet(0.0), left: neumann(0.0) }),
+            quote!(boundaries: { left: neumann(0.0, 1.0) }),
+            quote!(boundaries: { left: outflow }),
+            quote!(boundaries: [left]),
+        ] {
+            assert!(parse_boundaries(boundaries_arg(tokens), BOUNDARIES_2D).is_err());
+        }
+    }
+
+    #[test]
+    fn faces() {
+        let arg =
+            boundaries_arg(quote!(boundaries: { back: dirichlet(x * y), front: dirichlet(0) }));
+
+        let faces = parse_boundaries(arg, FACES_3D).unwrap();
+        let names: Vec<_> = faces.iter().map(|(b, _)| b.to_string()).collect();
+        assert_eq!(names, ["Back", "Front"]);
+
+        let arg = boundaries_arg(quote!(boundaries: { back: dirichlet(0) }));
+        assert!(parse_boundaries(arg, BOUNDARIES_2D).is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        let stencil: Expr = syn::parse_quote!([(-1, 0, 0), (0, 0, -1)]);
+        assert_eq!(
+            parse_offsets(stencil, 3).unwrap(),
+            [vec![-1, 0, 0], vec![0, 0, -1]]
+        );
+
+        let stencil: Expr = syn::parse_quote!([(-1, 0, 0), (0, -1)]);
+        assert!(parse_offsets(stencil, 3).is_err());
+
+        // 1D offsets are plain integers
+        let stencil: Expr = syn::parse_quote!([-1, 0, 1]);
+        assert_eq!(parse_offsets(stencil, 1).unwrap(), [[-1], [0], [1]]);
+    }
+
+    #[test]
+    fn scheme_args() {
+        let parse = |tokens: proc_macro2::TokenStream, num_dimensions, names| {
+            let args: CommaSeparatedArgs = syn::parse2(tokens).unwrap();
+            SchemeArgs::parse(&args, num_dimensions, names)
+        };
+
+        let args = parse(
+            quote!(equation: u_xx = 0, stencil: [-1, 0, 1], unknown: [-1, 0, 1]),
+            1,
+            BOUNDARIES_1D,
+        )
+        .unwrap();
+        assert_eq!(args.unknowns, [[-1], [0], [1]]);
+        assert!(args.time.is_none());
+
+        // The unknown defaults to the center of the stencil
+        let args = parse(
+            quote!(equation: u_t = u_xx, stencil: [(0, 0), (1, 0)], time: t),
+            2,
+            BOUNDARIES_2D,
+        )
+        .unwrap();
+        assert_eq!(args.unknowns, [[0, 0]]);
+        assert!(args.time.is_some());
+
+        for (tokens, num_dimensions) in [
+            (quote!(stencil: [(0, 0)]), 2),
+            (quote!(equation: u_xx = 0), 2),
+            (quote!(equation: u_xx = 0, stencil: [(0, 0)], max_dt: 1.), 2),
+            (
+                quote!(equation: u_t = 0, stencil: [(0, 0)], time: t, unknown: (0, 0)),
+                2,
+            ),
+            (
+                quote!(equation: u_xx = 0, stencil: [(0, 0)], unknown: (1, 0)),
+                2,
+            ),
+            (quote!(equation: u_t = 0, stencil: [(0, 0, 0)], time: t), 3),
+        ] {
+            assert!(parse(tokens, num_dimensions, FACES_3D).is_err());
+        }
+    }
+}
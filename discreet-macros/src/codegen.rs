@@ -0,0 +1,882 @@
+use discreet_common::algebra::{MeshExpr, Variable, weight_ident};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::Ident;
+
+use crate::args::{BoundaryDecl, Dimensions};
+
+/// The code needed to run one iteration of a scheme, which depends on whether it is explicit or implicit.
+pub struct Scheme {
+    /// Statements that run one iteration over the mesh.
+    pub iteration: TokenStream,
+    /// Methods of `FiniteDiff` used by `iteration`.
+    pub methods: TokenStream,
+}
+
+/// The kinds of mesh that the generated code handles. Each gets its own version of the scheme, as
+/// derivatives are scaled by the spacing on a simple grid, use weights from the local spacing on a
+/// rectilinear mesh, and are found with the chain rule on a curvilinear mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Domain {
+    Simple,
+    Rectilinear,
+    Complex,
+}
+
+impl Domain {
+    /// The pattern matching the scaling of this kind of mesh, binding what the scheme uses from it.
+    fn pattern(self) -> TokenStream {
+        match self {
+            Self::Simple => quote! {MeshScaling::SimpleGrid(dx, dy)},
+            Self::Rectilinear => quote! {MeshScaling::Rectilinear(..)},
+            Self::Complex => quote! {MeshScaling::ComplexPhysDomain(_)},
+        }
+    }
+
+    /// Why no scheme is generated for this kind of mesh, given the derivatives in the equation that the
+    /// stencil can't approximate on it.
+    pub fn unsupported(self, missing: &[String]) -> String {
+        let kind = match self {
+            Self::Simple => "uniform",
+            Self::Rectilinear => "rectilinear",
+            Self::Complex => "curvilinear",
+        };
+
+        format!(
+            "The stencil can't approximate {} on a {kind} mesh, so the scheme can't be used on one.",
+            missing.join(", ")
+        )
+    }
+
+    /// The name of a method for this kind of mesh, e.g. `iterate_point_simple_domain`.
+    fn method(self, name: &str) -> Ident {
+        match self {
+            Self::Simple => format_ident!("{name}_simple_domain"),
+            Self::Rectilinear => format_ident!("{name}_rectilinear_domain"),
+            Self::Complex => format_ident!("{name}_complex_domain"),
+        }
+    }
+
+    /// Statements reading the values besides the solution that `residual` uses at the node `(i, j)` that the
+    /// scheme is centered on.
+    fn node_values(self, residual: &MeshExpr, stencil: &[(isize, isize)]) -> TokenStream {
+        match self {
+            Self::Simple => quote! {},
+            Self::Rectilinear => {
+                // The weights for the nodes on each axis of the stencil, found from the spacing around the
+                // center
+                let weights = residual.weights().into_iter().map(|(variable, order)| {
+                    let (offsets, index, variable_path): (Vec<_>, _, _) = match variable {
+                        Variable::X => (
+                            stencil.iter().filter(|(_, j)| *j == 0).map(|(i, _)| *i).collect(),
+                            quote! {i},
+                            quote! {::discreet_common::algebra::Variable::X},
+                        ),
+                        Variable::Y => (
+                            stencil.iter().filter(|(i, _)| *i == 0).map(|(_, j)| *j).collect(),
+                            quote! {j},
+                            quote! {::discreet_common::algebra::Variable::Y},
+                        ),
+                        Variable::Z => unreachable!("2D schemes have no derivatives w.r.t. z"),
+                    };
+                    let names = offsets
+                        .iter()
+                        .map(|&offset| weight_ident(variable, order, offset));
+
+                    quote! {
+                        let [#(#names),*] =
+                            self.mesh.taylor_weights(#variable_path, #index, [#(#offsets),*], #order);
+                    }
+                });
+
+                quote! {#(#weights)*}
+            }
+            Self::Complex => quote! {
+                let (metric_p_x, metric_p_y, metric_q_x, metric_q_y) = self.mesh.get_metrics(i, j);
+                let [metric_p_xx, metric_p_xy, metric_p_yy, metric_q_xx, metric_q_xy, metric_q_yy] =
+                    self.mesh.get_metric_derivatives(i, j);
+            },
+        }
+    }
+}
+
+/// The residual of the discretised equation on each kind of mesh, or why there is no scheme for that kind of
+/// mesh, if the stencil can't approximate the derivatives in the equation on it.
+pub type Residuals = Vec<(Domain, Result<MeshExpr, String>)>;
+
+/// Matches on the scaling of the mesh, with `arm` giving the code for each kind of mesh that has a scheme.
+/// Kinds of mesh without a scheme panic with the reason.
+fn match_domains<T, E: AsRef<str>>(
+    parts: &[(Domain, Result<T, E>)],
+    arm: impl Fn(Domain, &T) -> TokenStream,
+) -> TokenStream {
+    let arms = parts.iter().map(|(domain, part)| {
+        let pattern = domain.pattern();
+        let body = match part {
+            Ok(part) => arm(*domain, part),
+            Err(reason) => {
+                let reason = reason.as_ref();
+                quote! {panic!(#reason)}
+            }
+        };
+
+        quote! {
+            #pattern => {
+                #body
+            }
+        }
+    });
+
+    quote! {
+        match *self.mesh.get_scaling() {
+            #(#arms)*
+        }
+    }
+}
+
+/// Statements that panic if `mesh` is a kind of mesh that the scheme can't be used on, so that this is found
+/// when `FiniteDiff` is created rather than when it is run.
+pub fn reject_unsupported(residuals: &Residuals) -> TokenStream {
+    let arms: Vec<_> = residuals
+        .iter()
+        .filter_map(|(domain, residual)| {
+            let reason = residual.as_ref().err()?;
+            let pattern = domain.pattern();
+            Some(quote! {#pattern => panic!(#reason),})
+        })
+        .collect();
+
+    if arms.is_empty() {
+        return quote! {};
+    }
+
+    quote! {
+        #[allow(unused_variables)]
+        match *mesh.get_scaling() {
+            #(#arms)*
+            _ => {}
+        }
+    }
+}
+
+/// The furthest offsets of a stencil from its center in each direction, which determine how far the center
+/// has to stay away from the edges of the mesh.
+#[derive(Clone, Copy, Debug)]
+pub struct StencilExtent {
+    pub min_i: isize,
+    pub max_i: isize,
+    pub min_j: isize,
+    pub max_j: isize,
+}
+
+impl StencilExtent {
+    pub fn new(stencil: &[(isize, isize)]) -> Self {
+        let mut extent = Self {
+            min_i: 0,
+            max_i: 0,
+            min_j: 0,
+            max_j: 0,
+        };
+
+        for &(i, j) in stencil {
+            extent.min_i = extent.min_i.min(i);
+            extent.max_i = extent.max_i.max(i);
+            extent.min_j = extent.min_j.min(j);
+            extent.max_j = extent.max_j.max(j);
+        }
+
+        extent
+    }
+
+    /// The range of centers along the first index, which depends on the size of the mesh.
+    pub fn i_range(&self) -> TokenStream {
+        let (min, max) = (self.min_i, self.max_i);
+        quote! {self.mesh.centers(::discreet_common::algebra::Variable::X, #min, #max)}
+    }
+
+    /// The range of centers along the second index, which depends on the size of the mesh.
+    pub fn j_range(&self) -> TokenStream {
+        let (min, max) = (self.min_j, self.max_j);
+        quote! {self.mesh.centers(::discreet_common::algebra::Variable::Y, #min, #max)}
+    }
+}
+
+/// Whether a sweep along an index should go from the highest index to the lowest, given the offsets along
+/// that index of the nodes that the unknown depends on. Sweeping towards the nodes that come later means
+/// they already have their new values when they are used, which is needed when marching in time. If nodes
+/// lie on both sides, the sweep goes in increasing order, as Gauss-Seidel would.
+pub fn sweeps_backwards(dependencies: impl Iterator<Item = isize>) -> bool {
+    let (mut before, mut after) = (false, false);
+    for offset in dependencies {
+        before |= offset < 0;
+        after |= offset > 0;
+    }
+
+    after && !before
+}
+
+/// Gives the statement computing the expressions in `hoisted` into the `scale_consts` array, and the
+/// statement that unpacks them into the `scale_const_N` variables that the hoisted expressions are replaced
+/// by.
+pub fn scale_consts(hoisted: &[MeshExpr]) -> (TokenStream, TokenStream, usize) {
+    let values = hoisted.iter().map(MeshExpr::render);
+    let names = (0..hoisted.len()).map(|k| format_ident!("scale_const_{k}"));
+    let len = hoisted.len();
+
+    (
+        quote! {let scale_consts: [f64; #len] = [#(#values),*];},
+        quote! {let [#(#names),*] = scale_consts;},
+        hoisted.len(),
+    )
+}
+
+/// The value of the unknown that satisfies an explicit scheme, with the hoisted constants it uses.
+struct ExplicitParts {
+    init: TokenStream,
+    unpack: TokenStream,
+    num_consts: usize,
+    node_values: TokenStream,
+    rhs_expr: TokenStream,
+}
+
+/// An explicit scheme, where the equation at each node is solved for a single unknown. As the value that
+/// satisfies the scheme at a node can be computed on its own, these schemes can also be iterated to solve
+/// steady problems with Jacobi, Gauss-Seidel or SOR.
+pub fn explicit_scheme(
+    residuals: &Residuals,
+    unknown: (isize, isize),
+    stencil: &[(isize, isize)],
+) -> Scheme {
+    let parts: Vec<_> = residuals
+        .iter()
+        .map(|(domain, residual)| {
+            let parts = residual.as_ref().map(|residual| {
+                let mut hoisted = Vec::new();
+                let rhs_expr = residual
+                    .clone()
+                    .find_root_linear(&MeshExpr::AtOffset(vec![unknown.0, unknown.1]))
+                    .collect_linear()
+                    .hoist_constants(&mut hoisted)
+                    .render();
+                let (init, unpack, num_consts) = scale_consts(&hoisted);
+
+                ExplicitParts {
+                    init,
+                    unpack,
+                    num_consts,
+                    node_values: domain.node_values(residual, stencil),
+                    rhs_expr,
+                }
+            });
+
+            (*domain, parts)
+        })
+        .collect();
+
+    let (ui, uj) = unknown;
+    let (get_unknown, set_unknown) = if unknown == (0, 0) {
+        (
+            quote! {self.mesh.get_at(i, j)},
+            quote! {self.mesh.set_at(i, j, v)},
+        )
+    } else {
+        (
+            quote! {self.mesh.get_at_offset(i, j, #ui, #uj)},
+            quote! {self.mesh.set_at_offset(i, j, #ui, #uj, v)},
+        )
+    };
+
+    let extent = StencilExtent::new(stencil);
+    let i_range = extent.i_range();
+    let j_range = extent.j_range();
+
+    // Rows are marched through one at a time, and the nodes in a row are visited so that the nodes of the
+    // same row that the unknown depends on are updated first.
+    let rows = if sweeps_backwards(stencil.iter().map(|&(_, j)| j - uj)) {
+        quote! {(#j_range).rev()}
+    } else {
+        j_range.clone()
+    };
+    let same_row = stencil.iter().filter(|&&(_, j)| j == uj);
+    let columns = if sweeps_backwards(same_row.map(|&(i, _)| i - ui)) {
+        quote! {(#i_range).rev()}
+    } else {
+        i_range.clone()
+    };
+
+    let iteration = match_domains(&parts, |domain, ExplicitParts { init, .. }| {
+        let iterate_point = domain.method("iterate_point");
+        quote! {
+            #init
+            let columns = #columns;
+
+            for j in #rows {
+                for i in columns.clone() {
+                    self.#iterate_point(i, j, scale_consts);
+                }
+
+                self.mesh
+                    .apply_boundary_conditions_in_row((j as isize + (#uj)) as usize);
+            }
+        }
+    });
+
+    let sweep = match_domains(&parts, |domain, ExplicitParts { init, .. }| {
+        let point_value = domain.method("point_value");
+        quote! {
+            #init
+            let columns = #i_range;
+            let indices = (#j_range).flat_map(|j| columns.clone().map(move |i| (i, j)));
+
+            if method.is_simultaneous() {
+                let updates: Vec<_> = indices
+                    .map(|(i, j)| {
+                        let v = self.#point_value(i, j, scale_consts);
+                        (i, j, method.relax(#get_unknown, v))
+                    })
+                    .collect();
+
+                for (i, j, v) in updates {
+                    #set_unknown;
+                }
+            } else {
+                for (i, j) in indices {
+                    let v = self.#point_value(i, j, scale_consts);
+                    let v = method.relax(#get_unknown, v);
+
+                    #set_unknown;
+                }
+            }
+        }
+    });
+
+    let point_methods = parts.iter().filter_map(|(domain, parts)| {
+        let ExplicitParts {
+            unpack,
+            num_consts,
+            node_values,
+            rhs_expr,
+            ..
+        } = parts.as_ref().ok()?;
+        let iterate_point = domain.method("iterate_point");
+        let point_value = domain.method("point_value");
+
+        Some(quote! {
+            fn #iterate_point(&mut self, i: usize, j: usize, scale_consts: [f64; #num_consts]) {
+                let v = self.#point_value(i, j, scale_consts);
+
+                #set_unknown;
+            }
+
+            /// The value of the unknown that satisfies the scheme centered at `(i, j)`.
+            #[allow(unused_variables)]
+            fn #point_value(&self, i: usize, j: usize, scale_consts: [f64; #num_consts]) -> f64 {
+                #unpack
+                #node_values
+                #rhs_expr
+            }
+        })
+    });
+
+    let solve = solve_method();
+
+    Scheme {
+        iteration,
+        methods: quote! {
+            #solve
+
+            #[allow(unused_variables)]
+            fn sweep(&mut self, method: ::discreet_common::iterative::IterativeMethod) {
+                #sweep
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            #(#point_methods)*
+        },
+    }
+}
+
+/// The body of `rhs` for the method of lines, which sets the rate of change at each node where the stencil
+/// can be centered from the values in `u`, given the rate on each kind of mesh.
+pub fn rhs_body(rates: &Residuals, stencil: &[(isize, isize)]) -> TokenStream {
+    let extent = StencilExtent::new(stencil);
+    let i_range = extent.i_range();
+    let j_range = extent.j_range();
+
+    match_domains(rates, |domain, rate| {
+        let mut hoisted = Vec::new();
+        let rate_expr = rate
+            .clone()
+            .hoist_constants(&mut hoisted)
+            .render_from(2, &format_ident!("u"));
+        let (init, unpack, _) = scale_consts(&hoisted);
+        let node_values = domain.node_values(rate, stencil);
+
+        quote! {
+            #init
+            #unpack
+            let columns = #i_range;
+
+            for j in #j_range {
+                for i in columns.clone() {
+                    #node_values
+                    du[self.mesh.get_index(i, j)] = #rate_expr;
+                }
+            }
+        }
+    })
+}
+
+/// The method `solve`, which iterates a steady problem to convergence with `sweep`.
+pub fn solve_method() -> TokenStream {
+    quote! {
+        /// Sweeps over the mesh with `method` until the largest residual of the scheme is below `tolerance`,
+        /// or `max_iters` sweeps have been done.
+        fn solve(
+            &mut self,
+            tolerance: f64,
+            max_iters: usize,
+            method: ::discreet_common::iterative::IterativeMethod,
+        ) -> ::discreet_common::iterative::ConvergenceReport {
+            let mut residuals = Vec::new();
+
+            for _ in 0..max_iters {
+                self.sweep(method);
+
+                let (_, max) = self.get_error_stats();
+                residuals.push(max);
+
+                if max < tolerance {
+                    break;
+                }
+            }
+
+            ::discreet_common::iterative::ConvergenceReport::new(residuals, tolerance)
+        }
+    }
+}
+
+/// The coefficients of the unknowns of an implicit scheme, and the rest of the equation moved to the right
+/// hand side, with the hoisted constants they use.
+struct ImplicitParts {
+    init: TokenStream,
+    unpack: TokenStream,
+    num_consts: usize,
+    node_values: TokenStream,
+    coefficients: Vec<TokenStream>,
+    known: TokenStream,
+}
+
+/// An implicit scheme, where the unknowns lie along a row of the mesh. The equations centered at each node
+/// of the row form a banded system, which is solved for the whole row at once. Steady problems are solved by
+/// line relaxation, solving each row with the rows around it held fixed.
+pub fn implicit_scheme(
+    residuals: &Residuals,
+    unknowns: &[(isize, isize)],
+    stencil: &[(isize, isize)],
+) -> Result<Scheme, String> {
+    let row_offset = unknowns[0].1;
+    if unknowns.iter().any(|&(_, j)| j != row_offset) {
+        return Err("The unknowns of an implicit scheme should all be on the same row.".into());
+    }
+    if !unknowns.contains(&(0, row_offset)) {
+        return Err(
+            "The unknowns of an implicit scheme should include the node in the same column as the center of the stencil."
+                .into(),
+        );
+    }
+
+    let mut parts = Vec::with_capacity(residuals.len());
+    for (domain, residual) in residuals {
+        let residual = match residual {
+            Ok(residual) => residual,
+            Err(reason) => {
+                parts.push((*domain, Err(reason)));
+                continue;
+            }
+        };
+
+        let mut hoisted = Vec::new();
+
+        let mut coefficients = Vec::with_capacity(unknowns.len());
+        for &(i, j) in unknowns {
+            let coefficient = residual
+                .differentiate(&MeshExpr::AtOffset(vec![i, j]))
+                .simplify();
+            if !coefficient.offsets().is_empty() {
+                return Err(
+                    "Implicit schemes need the equation to be linear in the unknowns.".into(),
+                );
+            }
+
+            coefficients.push(coefficient.hoist_constants(&mut hoisted).render());
+        }
+
+        let known = unknowns.iter().fold(residual.clone(), |expr, &(i, j)| {
+            expr.substitute(&MeshExpr::AtOffset(vec![i, j]), &MeshExpr::Constant(0.))
+        });
+        let known = MeshExpr::Negate(Box::new(known))
+            .simplify()
+            .collect_linear()
+            .hoist_constants(&mut hoisted)
+            .render();
+
+        let (init, unpack, num_consts) = scale_consts(&hoisted);
+        parts.push((
+            *domain,
+            Ok(ImplicitParts {
+                init,
+                unpack,
+                num_consts,
+                node_values: domain.node_values(residual, stencil),
+                coefficients,
+                known,
+            }),
+        ));
+    }
+
+    let offsets: Vec<_> = unknowns.iter().map(|&(i, _)| i).collect();
+    // Boundary conditions couple the boundary node with the two nodes next to it
+    let lower = unknowns.iter().map(|&(i, _)| -i).max().unwrap().max(2) as usize;
+    let upper = unknowns.iter().map(|&(i, _)| i).max().unwrap().max(2) as usize;
+    let cyclic_lower = unknowns.iter().map(|&(i, _)| -i).max().unwrap().max(0) as usize;
+    let cyclic_upper = unknowns.iter().map(|&(i, _)| i).max().unwrap().max(0) as usize;
+
+    let extent = StencilExtent::new(stencil);
+    let i_range = extent.i_range();
+    let j_range = extent.j_range();
+
+    let rows = if sweeps_backwards(stencil.iter().map(|&(_, j)| j - row_offset)) {
+        quote! {(#j_range).rev()}
+    } else {
+        j_range.clone()
+    };
+
+    let iteration = match_domains(&parts, |domain, ImplicitParts { init, .. }| {
+        let row_values = domain.method("row_values");
+        quote! {
+            #init
+
+            for j in #rows {
+                let values = self.#row_values(j, scale_consts);
+                self.set_row(j, values, ::discreet_common::iterative::IterativeMethod::GaussSeidel);
+            }
+        }
+    });
+
+    // Solving each row in turn with the rows next to it held fixed is line relaxation, the block version of
+    // the iterative methods for explicit schemes
+    let sweep = match_domains(&parts, |domain, ImplicitParts { init, .. }| {
+        let row_values = domain.method("row_values");
+        quote! {
+            #init
+
+            if method.is_simultaneous() {
+                let updates: Vec<_> = (#j_range)
+                    .map(|j| (j, self.#row_values(j, scale_consts)))
+                    .collect();
+
+                for (j, values) in updates {
+                    self.set_row(j, values, method);
+                }
+            } else {
+                for j in #j_range {
+                    let values = self.#row_values(j, scale_consts);
+                    self.set_row(j, values, method);
+                }
+            }
+        }
+    });
+
+    let row_methods = parts.iter().filter_map(|(domain, parts)| {
+        let ImplicitParts {
+            unpack,
+            num_consts,
+            node_values,
+            coefficients,
+            known,
+            ..
+        } = parts.as_ref().ok()?;
+        let row_values = domain.method("row_values");
+
+        Some(quote! {
+            /// The values of every node of the row solved for by the scheme centered on row `j`, which are
+            /// found at once. Nodes where the scheme can't be centered keep their values, unless they lie on a
+            /// boundary with a condition set, which is then solved for too.
+            #[allow(unused_variables)]
+            fn #row_values(&self, j: usize, scale_consts: [f64; #num_consts]) -> Vec<f64> {
+                #unpack
+
+                let width = self.mesh.width();
+
+                // Every node of a periodic row is a center, and the band wraps around the row. The last node
+                // is the same point as the first.
+                if self.mesh.is_periodic(::discreet_common::mesh2d::Boundary::Left) {
+                    let mut system = ::discreet_common::banded::CyclicBandedSystem::new(
+                        width - 1,
+                        #cyclic_lower,
+                        #cyclic_upper,
+                    );
+
+                    for i in 0..width - 1 {
+                        #node_values
+                        #(
+                            system.add(i, i as isize + (#offsets), #coefficients);
+                        )*
+                        system.set_rhs(i, #known);
+                    }
+
+                    let mut values = system.solve();
+                    values.push(values[0]);
+                    return values;
+                }
+
+                let centers = #i_range;
+                let row = (j as isize + (#row_offset)) as usize;
+
+                let mut system =
+                    ::discreet_common::banded::BandedSystem::new(width, #lower, #upper);
+
+                for i in 0..width {
+                    if centers.contains(&i) {
+                        #node_values
+                        #(
+                            let node = (i as isize + (#offsets)) as usize;
+                            system.set(i, node, #coefficients);
+                        )*
+                        system.set_rhs(i, #known);
+                        continue;
+                    }
+
+                    let condition = match i {
+                        0 => self
+                            .mesh
+                            .boundary_equation(::discreet_common::mesh2d::Boundary::Left, row),
+                        i if i == width - 1 => self
+                            .mesh
+                            .boundary_equation(::discreet_common::mesh2d::Boundary::Right, row),
+                        _ => None,
+                    };
+
+                    match condition {
+                        Some((terms, mut rhs)) => {
+                            for ((node_i, node_j), coefficient) in terms {
+                                if node_j == row {
+                                    system.set(i, node_i, coefficient);
+                                } else {
+                                    // Nodes on other rows are known
+                                    rhs -= coefficient * self.mesh.get_at(node_i, node_j);
+                                }
+                            }
+                            system.set_rhs(i, rhs);
+                        }
+                        None => {
+                            system.set(i, i, 1.);
+                            system.set_rhs(i, self.mesh.get_at(i, row));
+                        }
+                    }
+                }
+
+                system.solve()
+            }
+        })
+    });
+
+    let solve = solve_method();
+
+    Ok(Scheme {
+        iteration,
+        methods: quote! {
+            #solve
+
+            #[allow(unused_variables)]
+            fn sweep(&mut self, method: ::discreet_common::iterative::IterativeMethod) {
+                #sweep
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            /// Updates the row solved for by the scheme centered on row `j` to `values` with `method`.
+            fn set_row(
+                &mut self,
+                j: usize,
+                values: Vec<f64>,
+                method: ::discreet_common::iterative::IterativeMethod,
+            ) {
+                let row = (j as isize + (#row_offset)) as usize;
+
+                for (i, v) in values.into_iter().enumerate() {
+                    self.mesh.set_at(i, row, method.relax(self.mesh.get_at(i, row), v));
+                }
+            }
+
+            #(#row_methods)*
+        },
+    })
+}
+
+/// The method `get_error_stats`, which gives the mean and maximum absolute residual of the scheme over the
+/// nodes where the stencil lies within the mesh.
+pub fn error_stats(residuals: &Residuals, stencil: &[(isize, isize)]) -> TokenStream {
+    // Anything that is the same at every node is computed once rather than at each node
+    let parts: Vec<_> = residuals
+        .iter()
+        .map(|(domain, residual)| {
+            let parts = residual.as_ref().map(|residual| {
+                let mut hoisted = Vec::new();
+                let error_expr = residual
+                    .clone()
+                    .collect_linear()
+                    .hoist_constants(&mut hoisted)
+                    .render();
+
+                (
+                    scale_consts(&hoisted),
+                    domain.node_values(residual, stencil),
+                    error_expr,
+                )
+            });
+
+            (*domain, parts)
+        })
+        .collect();
+
+    let extent = StencilExtent::new(stencil);
+    let i_range = extent.i_range();
+    let j_range = extent.j_range();
+
+    let stats = match_domains(&parts, |_, ((init, unpack, _), node_values, error_expr)| {
+        quote! {
+            #init
+            #unpack
+
+            for (i, j) in indices {
+                #node_values
+                let error = (#error_expr).abs();
+
+                let total = mean * prev_elements + error;
+                prev_elements += 1.;
+                mean = total / prev_elements;
+
+                if error > max {
+                    max = error;
+                }
+            }
+        }
+    });
+
+    quote! {
+        #[allow(unused_variables)]
+        fn get_error_stats(&self) -> (f64, f64) {
+            let mut prev_elements = 0.;
+            let mut mean = 0.;
+            let mut max = 0.;
+
+            let indices = (#j_range).flat_map(|j| (#i_range).map(move |i| (i, j)));
+
+            #stats
+
+            (mean, max)
+        }
+    }
+}
+
+/// Statements that set the boundary conditions declared in the macro on `mesh`, with the constants taken
+/// from `consts`. Dirichlet values are kept as closures of the coordinates, so the mesh can reevaluate them.
+/// `boundary_type` is the path of the enum identifying the boundaries of the mesh.
+pub fn boundary_setup(
+    boundaries: &[(Ident, BoundaryDecl)],
+    dims: &Dimensions,
+    constants: &[&Ident],
+    boundary_type: TokenStream,
+) -> TokenStream {
+    let coords = dims.idents();
+
+    let conditions = boundaries.iter().map(|(boundary, condition)| {
+        let boundary = quote! {#boundary_type::#boundary};
+
+        match condition {
+            BoundaryDecl::Dirichlet(value) => quote! {
+                mesh.set_dirichlet(#boundary, move |#(#coords),*| #value);
+            },
+            BoundaryDecl::Neumann(value) => quote! {
+                mesh.set_boundary_condition(
+                    #boundary,
+                    ::discreet_common::mesh2d::BoundaryCondition::Neumann(#value),
+                );
+            },
+            BoundaryDecl::Robin(values) => {
+                let [a, b, value] = values.as_ref();
+                quote! {
+                    mesh.set_boundary_condition(
+                        #boundary,
+                        ::discreet_common::mesh2d::BoundaryCondition::Robin { a: #a, b: #b, g: #value },
+                    );
+                }
+            }
+        }
+    });
+
+    quote! {
+        let Constants { #(#constants),* } = consts;
+        #(#conditions)*
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use discreet_common::algebra::MeshExpr;
+
+    use super::{Domain, StencilExtent, implicit_scheme, sweeps_backwards};
+
+    #[test]
+    fn stencil_extent() {
+        let extent = StencilExtent::new(&[(-1, 0), (0, 0), (2, 0), (0, -1)]);
+
+        assert_eq!(
+            (extent.min_i, extent.max_i, extent.min_j, extent.max_j),
+            (-1, 2, -1, 0)
+        );
+    }
+
+    #[test]
+    fn sweep_direction() {
+        // Marching forwards in time
+        assert!(!sweeps_backwards([-1, 0, 0].into_iter()));
+        // Marching backwards, e.g. upwinding towards the left
+        assert!(sweeps_backwards([0, 1, 2].into_iter()));
+        // Elliptic problems depend on both sides
+        assert!(!sweeps_backwards([-1, 0, 1].into_iter()));
+        assert!(!sweeps_backwards([0].into_iter()));
+    }
+
+    #[test]
+    fn implicit_unknowns_on_different_rows() {
+        let residual = MeshExpr::Sum(vec![
+            MeshExpr::AtOffset(vec![0, 0]),
+            MeshExpr::AtOffset(vec![0, -1]),
+        ]);
+
+        let residuals = vec![(Domain::Simple, Ok(residual))];
+
+        assert!(implicit_scheme(&residuals, &[(0, 0), (0, -1)], &[(0, 0), (0, -1)]).is_err());
+    }
+
+    #[test]
+    fn implicit_nonlinear_in_unknowns() {
+        // u_(i-1) * u_i - u_i(j-1) = 0
+        let residual = MeshExpr::Sum(vec![
+            MeshExpr::Prod(vec![
+                MeshExpr::AtOffset(vec![-1, 0]),
+                MeshExpr::AtOffset(vec![0, 0]),
+            ]),
+            MeshExpr::Negate(Box::new(MeshExpr::AtOffset(vec![0, -1]))),
+        ]);
+        let stencil = [(-1, 0), (0, 0), (0, -1)];
+
+        let residuals = vec![(Domain::Simple, Ok(residual))];
+
+        assert!(implicit_scheme(&residuals, &[(-1, 0), (0, 0)], &stencil).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use discreet_common::algebra::MeshExpr;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::codegen::{Scheme, scale_consts, solve_method, sweeps_backwards};
+
+/// The range of nodes that the stencil can be centered on, which depends on the size of the mesh.
+fn center_range(stencil: &[isize]) -> TokenStream {
+    let min = stencil.iter().copied().min().unwrap_or(0).min(0);
+    let max = stencil.iter().copied().max().unwrap_or(0).max(0);
+
+    quote! {self.mesh.centers(::discreet_common::algebra::Variable::X, #min, #max)}
+}
+
+/// An explicit scheme on a 1D mesh, where the equation at each node is solved for a single unknown. The nodes
+/// are visited so that those the unknown depends on are updated first, which marches an initial value
+/// problem from the nodes before the first center, whose values are the initial conditions. The scheme can
+/// also be iterated to solve boundary value problems, including nonlinear ones.
+pub fn explicit_scheme(residual: &MeshExpr, unknown: isize, stencil: &[isize]) -> Scheme {
+    let mut hoisted = Vec::new();
+    let rhs_expr = residual
+        .clone()
+        .find_root_linear(&MeshExpr::AtOffset(vec![unknown]))
+        .collect_linear()
+        .hoist_constants(&mut hoisted)
+        .render_in(1);
+    let (init, unpack, num_consts) = scale_consts(&hoisted);
+
+    let (get_unknown, set_unknown) = if unknown == 0 {
+        (
+            quote! {self.mesh.get_at(i)},
+            quote! {self.mesh.set_at(i, v)},
+        )
+    } else {
+        (
+            quote! {self.mesh.get_at_offset(i, #unknown)},
+            quote! {self.mesh.set_at_offset(i, #unknown, v)},
+        )
+    };
+
+    let i_range = center_range(stencil);
+    let nodes = if sweeps_backwards(stencil.iter().map(|i| i - unknown)) {
+        quote! {(#i_range).rev()}
+    } else {
+        i_range.clone()
+    };
+
+    let iteration = quote! {
+        let dx = self.mesh.spacing();
+        #init
+
+        for i in #nodes {
+            self.iterate_point(i, scale_consts);
+        }
+    };
+
+    let solve = solve_method();
+
+    Scheme {
+        iteration,
+        methods: quote! {
+            #solve
+
+            #[allow(unused_variables)]
+            fn sweep(&mut self, method: ::discreet_common::iterative::IterativeMethod) {
+                let dx = self.mesh.spacing();
+                #init
+
+                if method.is_simultaneous() {
+                    let updates: Vec<_> = (#i_range)
+                        .map(|i| {
+                            let v = self.point_value(i, scale_consts);
+                            (i, method.relax(#get_unknown, v))
+                        })
+                        .collect();
+
+                    for (i, v) in updates {
+                        #set_unknown;
+                    }
+                } else {
+                    for i in #i_range {
+                        let v = self.point_value(i, scale_consts);
+                        let v = method.relax(#get_unknown, v);
+
+                        #set_unknown;
+                    }
+                }
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            fn iterate_point(&mut self, i: usize, scale_consts: [f64; #num_consts]) {
+                let v = self.point_value(i, scale_consts);
+
+                #set_unknown;
+            }
+
+            /// The value of the unknown that satisfies the scheme centered at `i`.
+            #[allow(unused_variables)]
+            fn point_value(&self, i: usize, scale_consts: [f64; #num_consts]) -> f64 {
+                #unpack
+                #rhs_expr
+            }
+        },
+    }
+}
+
+/// An implicit scheme on a 1D mesh, where the equations centered at every node are solved together with the
+/// conditions at the ends as a single banded system. This solves linear two-point boundary value problems
+/// directly, and with a three point stencil and Dirichlet conditions, the system is tridiagonal.
+pub fn implicit_scheme(
+    residual: &MeshExpr,
+    unknowns: &[isize],
+    stencil: &[isize],
+) -> Result<Scheme, String> {
+    if !unknowns.contains(&0) {
+        return Err(
+            "The unknowns of an implicit scheme should include the center of the stencil.".into(),
+        );
+    }
+
+    let mut hoisted = Vec::new();
+
+    let mut coefficients = Vec::with_capacity(unknowns.len());
+    for &i in unknowns {
+        let coefficient = residual
+            .differentiate(&MeshExpr::AtOffset(vec![i]))
+            .simplify();
+        if !coefficient.offsets().is_empty() {
+            return Err("Implicit schemes need the equation to be linear in the unknowns.".into());
+        }
+
+        coefficients.push(coefficient.hoist_constants(&mut hoisted).render_in(1));
+    }
+
+    let known = unknowns.iter().fold(residual.clone(), |expr, &i| {
+        expr.substitute(&MeshExpr::AtOffset(vec![i]), &MeshExpr::Constant(0.))
+    });
+    let known = MeshExpr::Negate(Box::new(known))
+        .simplify()
+        .collect_linear()
+        .hoist_constants(&mut hoisted)
+        .render_in(1);
+
+    let (init, unpack, num_consts) = scale_consts(&hoisted);
+
+    let lower = unknowns.iter().map(|i| -i).max().unwrap().max(0) as usize;
+    let upper = unknowns.iter().copied().max().unwrap().max(0) as usize;
+    let i_range = center_range(stencil);
+
+    let solve = solve_method();
+
+    Ok(Scheme {
+        iteration: quote! {
+            let dx = self.mesh.spacing();
+            #init
+
+            for (i, v) in self.system_values(scale_consts).into_iter().enumerate() {
+                self.mesh.set_at(i, v);
+            }
+        },
+        methods: quote! {
+            #solve
+
+            /// As the whole mesh is solved for at once, a single Jacobi or Gauss-Seidel sweep solves the
+            /// problem, and SOR relaxes towards that solution.
+            #[allow(unused_variables)]
+            fn sweep(&mut self, method: ::discreet_common::iterative::IterativeMethod) {
+                let dx = self.mesh.spacing();
+                #init
+
+                for (i, v) in self.system_values(scale_consts).into_iter().enumerate() {
+                    self.mesh.set_at(i, method.relax(self.mesh.get_at(i), v));
+                }
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            /// The values of every node, which are solved for at once. Nodes where the scheme can't be
+            /// centered keep their values, unless they are at an end with a condition set, which is then
+            /// solved for too.
+            #[allow(unused_variables)]
+            fn system_values(&self, scale_consts: [f64; #num_consts]) -> Vec<f64> {
+                #unpack
+
+                let centers = #i_range;
+                let len = self.mesh.len();
+                // Conditions on the normal derivative couple the end with the two nodes next to it
+                let reach = self.mesh.boundary_reach();
+
+                let mut system = ::discreet_common::banded::BandedSystem::new(
+                    len,
+                    reach.max(#lower),
+                    reach.max(#upper),
+                );
+
+                for i in 0..len {
+                    if centers.contains(&i) {
+                        #(
+                            let node = (i as isize + (#unknowns)) as usize;
+                            system.set(i, node, #coefficients);
+                        )*
+                        system.set_rhs(i, #known);
+                        continue;
+                    }
+
+                    let condition = match i {
+                        0 => self
+                            .mesh
+                            .boundary_equation(::discreet_common::mesh1d::Boundary::Left),
+                        i if i == len - 1 => self
+                            .mesh
+                            .boundary_equation(::discreet_common::mesh1d::Boundary::Right),
+                        _ => None,
+                    };
+
+                    match condition {
+                        Some((terms, rhs)) => {
+                            for (node, coefficient) in terms {
+                                system.set(i, node, coefficient);
+                            }
+                            system.set_rhs(i, rhs);
+                        }
+                        None => {
+                            system.set(i, i, 1.);
+                            system.set_rhs(i, self.mesh.get_at(i));
+                        }
+                    }
+                }
+
+                system.solve()
+            }
+        },
+    })
+}
+
+/// The body of `rhs` for the method of lines, which sets the rate of change at each node where the stencil
+/// can be centered from the values in `u`.
+pub fn rhs_body(rate: &MeshExpr, stencil: &[isize]) -> TokenStream {
+    let mut hoisted = Vec::new();
+    let rate_expr = rate
+        .clone()
+        .hoist_constants(&mut hoisted)
+        .render_from(1, &format_ident!("u"));
+    let (init, unpack, _) = scale_consts(&hoisted);
+
+    let i_range = center_range(stencil);
+
+    quote! {
+        let dx = self.mesh.spacing();
+        #init
+        #unpack
+
+        for i in #i_range {
+            du[self.mesh.get_index(i)] = #rate_expr;
+        }
+    }
+}
+
+/// The method `get_error_stats`, which gives the mean and maximum absolute residual of the scheme over the
+/// nodes where the stencil lies within the mesh.
+pub fn error_stats(residual: &MeshExpr, stencil: &[isize]) -> TokenStream {
+    let mut hoisted = Vec::new();
+    let error_expr = residual
+        .clone()
+        .collect_linear()
+        .hoist_constants(&mut hoisted)
+        .render_in(1);
+    let (init, unpack, _) = scale_consts(&hoisted);
+
+    let i_range = center_range(stencil);
+
+    quote! {
+        #[allow(unused_variables)]
+        fn get_error_stats(&self) -> (f64, f64) {
+            let mut prev_elements = 0.;
+            let mut mean = 0.;
+            let mut max = 0.;
+
+            let dx = self.mesh.spacing();
+            #init
+            #unpack
+
+            for i in #i_range {
+                let error = (#error_expr).abs();
+
+                let total = mean * prev_elements + error;
+                prev_elements += 1.;
+                mean = total / prev_elements;
+
+                if error > max {
+                    max = error;
+                }
+            }
+
+            (mean, max)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use discreet_common::algebra::MeshExpr;
+
+    use super::implicit_scheme;
+
+    #[test]
+    fn implicit_unknowns() {
+        let residual = MeshExpr::Sum(vec![
+            MeshExpr::AtOffset(vec![-1]),
+            MeshExpr::AtOffset(vec![0]),
+            MeshExpr::AtOffset(vec![1]),
+        ]);
+
+        assert!(implicit_scheme(&residual, &[-1, 0, 1], &[-1, 0, 1]).is_ok());
+        assert!(implicit_scheme(&residual, &[-1, 1], &[-1, 0, 1]).is_err());
+
+        let nonlinear = MeshExpr::Prod(vec![
+            MeshExpr::AtOffset(vec![0]),
+            MeshExpr::AtOffset(vec![1]),
+        ]);
+        assert!(implicit_scheme(&nonlinear, &[0, 1], &[0, 1]).is_err());
+    }
+}
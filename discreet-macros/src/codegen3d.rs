@@ -0,0 +1,204 @@
+use discreet_common::algebra::MeshExpr;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::Ident;
+
+use crate::{
+    args::{BoundaryDecl, Dimensions},
+    codegen::{Scheme, scale_consts, solve_method, sweeps_backwards},
+};
+
+/// The ranges of centers of the stencil along each index of the mesh, which depend on the size of the mesh.
+fn center_ranges(stencil: &[Vec<isize>]) -> [TokenStream; 3] {
+    std::array::from_fn(|axis| {
+        let min = stencil.iter().map(|o| o[axis]).min().unwrap_or(0).min(0);
+        let max = stencil.iter().map(|o| o[axis]).max().unwrap_or(0).max(0);
+        let variable = format_ident!("{}", ["X", "Y", "Z"][axis]);
+
+        quote! {self.mesh.centers(::discreet_common::algebra::Variable::#variable, #min, #max)}
+    })
+}
+
+/// An explicit scheme on a 3D mesh, where the equation at each node is solved for a single unknown. Layers
+/// of constant third index are marched through in turn, so when the third index is time, each layer is
+/// found from the previous ones. As in 2D, the scheme can also be iterated to solve steady problems.
+pub fn explicit_scheme(residual: &MeshExpr, unknown: &[isize], stencil: &[Vec<isize>]) -> Scheme {
+    let mut hoisted = Vec::new();
+    let rhs_expr = residual
+        .clone()
+        .find_root_linear(&MeshExpr::AtOffset(unknown.to_vec()))
+        .collect_linear()
+        .hoist_constants(&mut hoisted)
+        .render_in(3);
+    let (init, unpack, num_consts) = scale_consts(&hoisted);
+
+    let &[ui, uj, uk] = unknown else {
+        unreachable!("Offsets on a 3D mesh have 3 components")
+    };
+    let set_unknown = quote! {self.mesh.set_at_offset(i, j, k, #ui, #uj, #uk, v)};
+    let get_unknown = quote! {self.mesh.get_at_offset(i, j, k, #ui, #uj, #uk)};
+
+    let [i_range, j_range, k_range] = center_ranges(stencil);
+
+    // Nodes are visited so that the nodes of the same layer, and of the same row within it, that the unknown
+    // depends on are updated first.
+    let order = |range: &TokenStream, offsets: Vec<isize>| {
+        if sweeps_backwards(offsets.into_iter()) {
+            quote! {(#range).rev()}
+        } else {
+            range.clone()
+        }
+    };
+    let layers = order(&k_range, stencil.iter().map(|o| o[2] - uk).collect());
+    let same_layer = stencil.iter().filter(|o| o[2] == uk);
+    let rows = order(&j_range, same_layer.clone().map(|o| o[1] - uj).collect());
+    let same_row = same_layer.filter(|o| o[1] == uj);
+    let columns = order(&i_range, same_row.map(|o| o[0] - ui).collect());
+
+    let iteration = quote! {
+        let [dx, dy, dz] = self.mesh.spacing();
+        #init
+        let rows = #rows;
+        let columns = #columns;
+
+        for k in #layers {
+            for j in rows.clone() {
+                for i in columns.clone() {
+                    self.iterate_point(i, j, k, scale_consts);
+                }
+            }
+
+            self.mesh
+                .apply_boundary_conditions_in_layer((k as isize + (#uk)) as usize);
+        }
+    };
+
+    let solve = solve_method();
+
+    Scheme {
+        iteration,
+        methods: quote! {
+            #solve
+
+            #[allow(unused_variables)]
+            fn sweep(&mut self, method: ::discreet_common::iterative::IterativeMethod) {
+                let [dx, dy, dz] = self.mesh.spacing();
+                #init
+                let columns = #i_range;
+                let rows = #j_range;
+                let indices = (#k_range).flat_map(|k| {
+                    let columns = columns.clone();
+                    rows.clone().flat_map(move |j| columns.clone().map(move |i| (i, j, k)))
+                });
+
+                if method.is_simultaneous() {
+                    let updates: Vec<_> = indices
+                        .map(|(i, j, k)| {
+                            let v = self.point_value(i, j, k, scale_consts);
+                            (i, j, k, method.relax(#get_unknown, v))
+                        })
+                        .collect();
+
+                    for (i, j, k, v) in updates {
+                        #set_unknown;
+                    }
+                } else {
+                    for (i, j, k) in indices {
+                        let v = self.point_value(i, j, k, scale_consts);
+                        let v = method.relax(#get_unknown, v);
+
+                        #set_unknown;
+                    }
+                }
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            fn iterate_point(&mut self, i: usize, j: usize, k: usize, scale_consts: [f64; #num_consts]) {
+                let v = self.point_value(i, j, k, scale_consts);
+
+                #set_unknown;
+            }
+
+            /// The value of the unknown that satisfies the scheme centered at `(i, j, k)`.
+            #[allow(unused_variables)]
+            fn point_value(&self, i: usize, j: usize, k: usize, scale_consts: [f64; #num_consts]) -> f64 {
+                #unpack
+                #rhs_expr
+            }
+        },
+    }
+}
+
+/// The method `get_error_stats`, which gives the mean and maximum absolute residual of the scheme over the
+/// nodes where the stencil lies within the mesh.
+pub fn error_stats(residual: &MeshExpr, stencil: &[Vec<isize>]) -> TokenStream {
+    let mut hoisted = Vec::new();
+    let error_expr = residual
+        .clone()
+        .collect_linear()
+        .hoist_constants(&mut hoisted)
+        .render_in(3);
+    let (init, unpack, _) = scale_consts(&hoisted);
+
+    let [i_range, j_range, k_range] = center_ranges(stencil);
+
+    quote! {
+        #[allow(unused_variables)]
+        fn get_error_stats(&self) -> (f64, f64) {
+            let mut prev_elements = 0.;
+            let mut mean = 0.;
+            let mut max = 0.;
+
+            let [dx, dy, dz] = self.mesh.spacing();
+            #init
+            #unpack
+
+            for k in #k_range {
+                for j in #j_range {
+                    for i in #i_range {
+                        let error = (#error_expr).abs();
+
+                        let total = mean * prev_elements + error;
+                        prev_elements += 1.;
+                        mean = total / prev_elements;
+
+                        if error > max {
+                            max = error;
+                        }
+                    }
+                }
+            }
+
+            (mean, max)
+        }
+    }
+}
+
+/// Statements that set the conditions declared in the macro on the faces of `mesh`, with the constants taken
+/// from `consts`. Only Dirichlet conditions can be set on a 3D mesh.
+pub fn boundary_setup(
+    boundaries: &[(Ident, BoundaryDecl)],
+    dims: &Dimensions,
+    constants: &[&Ident],
+) -> syn::Result<TokenStream> {
+    let coords = dims.idents();
+
+    let conditions = boundaries
+        .iter()
+        .map(|(face, condition)| match condition {
+            BoundaryDecl::Dirichlet(value) => Ok(quote! {
+                mesh.set_dirichlet(::discreet_common::mesh3d::Face::#face, move |#(#coords),*| #value);
+            }),
+            BoundaryDecl::Neumann(_) | BoundaryDecl::Robin(_) => Err(syn::Error::new(
+                face.span(),
+                "Only Dirichlet conditions can be set on the faces of a 3D mesh.",
+            )),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        let Constants { #(#constants),* } = consts;
+        #(#conditions)*
+    })
+}
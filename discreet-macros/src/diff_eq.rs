@@ -1,45 +1,223 @@
 use discreet_common::algebra::{Expression, Variable};
+use proc_macro2::{Delimiter, Group, Ident, TokenStream, TokenTree};
+use quote::ToTokens;
 use syn::{
     BinOp, Expr, ExprAssign, ExprBinary, ExprCall, ExprLit, ExprParen, ExprPath, ExprUnary, Lit,
-    LitInt, spanned::Spanned,
+    LitInt, UnOp, spanned::Spanned,
 };
 
-pub fn parse_pde(expr: Expr) -> syn::Result<Expression> {
+use crate::args::Dimensions;
+
+pub fn parse_pde(expr: Expr, dims: &Dimensions) -> syn::Result<Expression> {
+    let stream = rewrite_leibniz(expr.into_token_stream())?;
+    let expr: Expr = syn::parse2(rebind_exponents(stream))?;
+
     match expr {
         Expr::Assign(ExprAssign { left, right, .. }) => {
-            let right_span = right.span();
+            let lhs = parse_pde_expr(*left, dims)?;
+
             if let Expr::Lit(ExprLit {
                 lit: Lit::Int(litint),
                 ..
-            }) = *right
+            }) = &*right
+                && litint.base10_parse::<isize>()? == 0
             {
-                if litint.base10_parse::<isize>()? == 0 {
-                    return parse_pde_expr(*left);
-                }
+                return Ok(lhs);
             }
-            Err(syn::Error::new(
-                right_span,
-                "Expected the RHS of the PDE to be an integer value of zero.",
-            ))
+
+            // Anything on the RHS is moved over, so that the residual `lhs - rhs` should be zero
+            let rhs = parse_pde_expr(*right, dims)?;
+            Ok(difference(lhs, rhs))
         }
         _ => Err(syn::Error::new(
             expr.span(),
-            "Expected the PDE to be formatted as an equation of the form `lhs = 0`.",
+            "Expected the PDE to be formatted as an equation of the form `lhs = rhs`.",
         )),
     }
 }
 
-fn parse_pde_expr(expr: Expr) -> syn::Result<Expression> {
+fn difference(left: Expression, right: Expression) -> Expression {
+    let mut sum = Vec::new();
+    match left {
+        Expression::Sum(terms) => {
+            sum.extend(terms);
+        }
+        other => {
+            sum.push(other);
+        }
+    }
+    match right {
+        Expression::Sum(terms) => {
+            let terms = terms
+                .into_iter()
+                .map(|exp| Expression::Negate(Box::new(exp)));
+            sum.extend(terms);
+        }
+        other => {
+            sum.push(Expression::Negate(Box::new(other)));
+        }
+    }
+    Expression::Sum(sum)
+}
+
+/// Derivatives in Leibniz notation (`du/dt`, `d2u/dx2`, `d2u/dxdy`) can't be recognised after parsing,
+/// since `nu * d2u/dx2` is parsed as `(nu * d2u) / dx2`. They are therefore rewritten into the
+/// equivalent subscript notation (`u_t`, `u_xx`, `u_xy`) before the equation is parsed.
+fn rewrite_leibniz(stream: TokenStream) -> syn::Result<TokenStream> {
+    let mut tokens: Vec<TokenTree> = Vec::new();
+    let mut iter = stream.into_iter().peekable();
+
+    while let Some(token) = iter.next() {
+        match token {
+            TokenTree::Group(group) => {
+                let mut rewritten = Group::new(group.delimiter(), rewrite_leibniz(group.stream())?);
+                rewritten.set_span(group.span());
+                tokens.push(TokenTree::Group(rewritten));
+            }
+            TokenTree::Ident(numerator)
+                if leibniz_order(&numerator).is_some()
+                    && matches!(iter.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '/') =>
+            {
+                let order = leibniz_order(&numerator).unwrap();
+                let slash = iter.next().unwrap();
+
+                let Some(TokenTree::Ident(denominator)) = iter.next() else {
+                    return Err(syn::Error::new(
+                        slash.span(),
+                        "Expected a derivative in Leibniz notation to be of the form `du/dx` or `d2u/dxdy`.",
+                    ));
+                };
+
+                let vars = leibniz_variables(&denominator)?;
+                if vars.len() != order {
+                    return Err(syn::Error::new(
+                        denominator.span(),
+                        format!(
+                            "The denominator of this derivative is of order {}, while the numerator is of order {order}.",
+                            vars.len()
+                        ),
+                    ));
+                }
+
+                let ident = Ident::new(&format!("u_{vars}"), numerator.span());
+                tokens.push(TokenTree::Ident(ident));
+            }
+            other => tokens.push(other),
+        }
+    }
+
+    Ok(tokens.into_iter().collect())
+}
+
+/// Returns the order of a numerator in Leibniz notation, i.e. `1` for `du` and `N` for `dNu`.
+fn leibniz_order(ident: &Ident) -> Option<usize> {
+    let string = ident.to_string();
+    let order = string.strip_prefix('d')?.strip_suffix('u')?;
+
+    if order.is_empty() {
+        Some(1)
+    } else if order.chars().all(|c| c.is_ascii_digit()) {
+        order.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Turns the denominator of a derivative in Leibniz notation into the variables it's taken with
+/// respect to, in the same format as the subscript notation (e.g. `dx2dy` becomes `xxy`).
+fn leibniz_variables(ident: &Ident) -> syn::Result<String> {
+    let error = || {
+        syn::Error::new(
+            ident.span(),
+            "Expected the denominator of the derivative to be of the form `dx`, `dx2` or `dxdy`.",
+        )
+    };
+
+    let string = ident.to_string();
+    let mut chars = string.chars().peekable();
+    let mut vars = String::new();
+
+    while let Some(d) = chars.next() {
+        let var = chars.next().ok_or_else(error)?;
+        if d != 'd' || !var.is_alphabetic() {
+            return Err(error());
+        }
+
+        let mut power = String::new();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            power.push(c);
+        }
+        let power = if power.is_empty() {
+            1
+        } else {
+            power.parse().map_err(|_| error())?
+        };
+
+        vars.extend(std::iter::repeat_n(var, power));
+    }
+
+    Ok(vars)
+}
+
+/// Rust gives `^` a lower precedence than the arithmetic operators, so `c^2 * u_xx` would be parsed
+/// as `c ^ (2 * u_xx)`. To get the usual mathematical meaning, every `^` is wrapped in parentheses
+/// together with the token trees directly next to it before the equation is parsed, which makes
+/// exponentiation bind tighter than any other operator (apart from a minus sign on the exponent).
+fn rebind_exponents(stream: TokenStream) -> TokenStream {
+    let mut tokens: Vec<TokenTree> = Vec::new();
+    let mut iter = stream.into_iter();
+
+    while let Some(token) = iter.next() {
+        match token {
+            TokenTree::Group(group) => {
+                let mut rebound = Group::new(group.delimiter(), rebind_exponents(group.stream()));
+                rebound.set_span(group.span());
+                tokens.push(TokenTree::Group(rebound));
+            }
+            TokenTree::Punct(caret) if caret.as_char() == '^' && !tokens.is_empty() => {
+                let base = tokens.pop().unwrap();
+                let mut pow = vec![base, TokenTree::Punct(caret)];
+
+                let mut exponent = iter.next();
+                if let Some(TokenTree::Punct(minus)) = &exponent
+                    && minus.as_char() == '-'
+                {
+                    pow.push(exponent.unwrap());
+                    exponent = iter.next();
+                }
+                pow.extend(exponent.map(|e| match e {
+                    TokenTree::Group(group) => {
+                        let mut rebound =
+                            Group::new(group.delimiter(), rebind_exponents(group.stream()));
+                        rebound.set_span(group.span());
+                        TokenTree::Group(rebound)
+                    }
+                    other => other,
+                }));
+
+                let span = pow[0].span();
+                let mut group = Group::new(Delimiter::Parenthesis, pow.into_iter().collect());
+                group.set_span(span);
+                tokens.push(TokenTree::Group(group));
+            }
+            other => tokens.push(other),
+        }
+    }
+
+    tokens.into_iter().collect()
+}
+
+fn parse_pde_expr(expr: Expr, dims: &Dimensions) -> syn::Result<Expression> {
     match expr {
-        Expr::Binary(expr) => parse_binop(expr),
-        Expr::Unary(expr) => parse_unary(expr),
+        Expr::Binary(expr) => parse_binop(expr, dims),
+        Expr::Unary(expr) => parse_unary(expr, dims),
         Expr::Call(expr) => Err(syn::Error::new(
             expr.span(),
             "The PDE should not contain any function calls. If you need to use a function that isn't the function you're solving for, you should simply use the function's identifier. ",
         )),
         Expr::Lit(expr) => parse_literal(expr),
-        Expr::Paren(expr) => parse_parenthesized(expr),
-        Expr::Path(expr) => parse_path(expr),
+        Expr::Paren(expr) => parse_parenthesized(expr, dims),
+        Expr::Path(expr) => parse_path(expr, dims),
         _ => Err(syn::Error::new(
             expr.span(),
             "Unexpected type of expression in equation.",
@@ -47,9 +225,16 @@ fn parse_pde_expr(expr: Expr) -> syn::Result<Expression> {
     }
 }
 
-fn parse_binop(expr: ExprBinary) -> syn::Result<Expression> {
-    let left = parse_pde_expr(*expr.left)?;
-    let right = parse_pde_expr(*expr.right)?;
+fn parse_binop(expr: ExprBinary, dims: &Dimensions) -> syn::Result<Expression> {
+    // We treat `^` as exponentiation
+    if let BinOp::BitXor(_) = expr.op {
+        let base = parse_pde_expr(*expr.left, dims)?;
+        let exponent = parse_exponent(*expr.right)?;
+        return Ok(Expression::Pow(Box::new(base), exponent));
+    }
+
+    let left = parse_pde_expr(*expr.left, dims)?;
+    let right = parse_pde_expr(*expr.right, dims)?;
 
     match expr.op {
         BinOp::Add(_) => {
@@ -72,29 +257,7 @@ fn parse_binop(expr: ExprBinary) -> syn::Result<Expression> {
             }
             Ok(Expression::Sum(sum))
         }
-        BinOp::Sub(_) => {
-            let mut sum = Vec::new();
-            match left {
-                Expression::Sum(terms) => {
-                    sum.extend(terms);
-                }
-                other => {
-                    sum.push(other);
-                }
-            }
-            match right {
-                Expression::Sum(mut terms) => {
-                    let terms = terms
-                        .into_iter()
-                        .map(|exp| Expression::Negate(Box::new(exp)));
-                    sum.extend(terms);
-                }
-                other => {
-                    sum.push(Expression::Negate(Box::new(other)));
-                }
-            }
-            Ok(Expression::Sum(sum))
-        }
+        BinOp::Sub(_) => Ok(difference(left, right)),
         BinOp::Mul(_) => {
             let mut prod = Vec::new();
             match left {
@@ -126,7 +289,7 @@ fn parse_binop(expr: ExprBinary) -> syn::Result<Expression> {
                 }
             }
             match right {
-                Expression::Prod(mut terms) => {
+                Expression::Prod(terms) => {
                     let terms = terms
                         .into_iter()
                         .map(|exp| Expression::Reciprocal(Box::new(exp)));
@@ -136,35 +299,67 @@ fn parse_binop(expr: ExprBinary) -> syn::Result<Expression> {
                     prod.push(Expression::Reciprocal(Box::new(other)));
                 }
             }
-            Ok(Expression::Sum(prod))
+            Ok(Expression::Prod(prod))
         }
-        // We treat `^` as exponentiation
-        BinOp::BitXor(_) => Ok(Expression::Constant(42.0)),
         x => Err(syn::Error::new(x.span(), "Unexpected operator in PDE")),
     }
 }
 
-fn parse_unary(expr: ExprUnary) -> syn::Result<Expression> {
-    let operand = parse_pde_expr(*expr.expr)?;
-    Ok(Expression::Constant(42.0))
+/// Exponents have to be known when generating the code, so only numeric literals are accepted.
+fn parse_exponent(expr: Expr) -> syn::Result<f64> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(i), ..
+        }) => i.base10_parse(),
+        Expr::Lit(ExprLit {
+            lit: Lit::Float(f), ..
+        }) => f.base10_parse(),
+        Expr::Paren(ExprParen { expr, .. }) => parse_exponent(*expr),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => Ok(-parse_exponent(*expr)?),
+        other => Err(syn::Error::new(
+            other.span(),
+            "Exponents should be integer or floating point literals.",
+        )),
+    }
+}
+
+fn parse_unary(expr: ExprUnary, dims: &Dimensions) -> syn::Result<Expression> {
+    match expr.op {
+        UnOp::Neg(_) => Ok(Expression::Negate(Box::new(parse_pde_expr(
+            *expr.expr, dims,
+        )?))),
+        UnOp::Not(op) => Err(syn::Error::new(
+            op.span(),
+            "Logical negation is not allowed in the PDE. Use `-` to negate a term.",
+        )),
+        op => Err(syn::Error::new(
+            op.span(),
+            "Unexpected unary operator in PDE",
+        )),
+    }
 }
 
 fn parse_literal(expr: ExprLit) -> syn::Result<Expression> {
     match expr.lit {
         Lit::Float(v) => Ok(Expression::Constant(v.base10_parse()?)),
+        Lit::Int(v) => Ok(Expression::Constant(v.base10_parse()?)),
         _ => Err(syn::Error::new(
             expr.span(),
-            "Only floating point literals are allowed here.",
+            "Only integer and floating point literals are allowed here.",
         )),
     }
 }
 
-fn parse_parenthesized(expr: ExprParen) -> syn::Result<Expression> {
-    parse_pde_expr(*expr.expr)
+fn parse_parenthesized(expr: ExprParen, dims: &Dimensions) -> syn::Result<Expression> {
+    parse_pde_expr(*expr.expr, dims)
 }
 
 /// This corresponds to identifiers.
-fn parse_path(expr: ExprPath) -> syn::Result<Expression> {
+fn parse_path(expr: ExprPath, dims: &Dimensions) -> syn::Result<Expression> {
     let span = expr.span();
     if expr.path.segments.len() != 1 {
         return Err(syn::Error::new(
@@ -182,14 +377,17 @@ fn parse_path(expr: ExprPath) -> syn::Result<Expression> {
 
         let mut vars = Vec::with_capacity(vars_string.len());
         for c in vars_string.chars() {
-            match Variable::from_char(c) {
+            match dims.variable(c) {
                 Some(v) => {
                     vars.push(v);
                 }
                 None => {
                     return Err(syn::Error::new(
                         span,
-                        "Differentiation with respect to unknown variable. Should be x, y, z.",
+                        format!(
+                            "Differentiation with respect to unknown variable `{c}`. Should be one of {}.",
+                            dims.names()
+                        ),
                     ));
                 }
             }
@@ -209,23 +407,239 @@ fn parse_path(expr: ExprPath) -> syn::Result<Expression> {
 
 #[cfg(test)]
 mod test {
-    use discreet_common::algebra::Expression;
-    use quote::quote;
+    use discreet_common::algebra::{Expression, Variable};
+    use quote::{format_ident, quote};
     use syn::Expr;
 
-    use crate::diff_eq::parse_pde;
+    use crate::{
+        args::{Dimensions, parse_dimensions},
+        diff_eq::parse_pde,
+    };
 
     #[test]
     fn example1() {
         let stream = quote! {du/dt - nu * d2u/dx2 - 2 * u = 0};
         let expr: Expr = syn::parse2(stream).unwrap();
+        let dims = parse_dimensions(syn::parse2(quote! {(x, t)}).unwrap(), 2).unwrap();
+
+        assert_eq!(
+            parse_pde(expr, &dims).unwrap(),
+            Expression::Sum(vec![
+                Expression::Derivative(Variable::Y, 1),
+                Expression::Negate(Box::new(Expression::Prod(vec![
+                    Expression::SymbolicConstant(format_ident!("nu")),
+                    Expression::Derivative(Variable::X, 2),
+                ]))),
+                Expression::Negate(Box::new(Expression::Prod(vec![
+                    Expression::Constant(2.),
+                    Expression::SolutionVal,
+                ]))),
+            ])
+        );
+    }
 
-        match parse_pde(expr) {
-            Ok(e) => {
-                assert_eq!(e, Expression::Constant(42.0))
+    #[test]
+    fn unknown_dimension() {
+        let stream = quote! {u_t + u_x = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        let err = parse_pde(expr, &Dimensions::default()).unwrap_err();
+        assert!(err.to_string().contains("unknown variable `t`"));
+    }
+
+    #[test]
+    fn exponents_bind_tighter_than_products() {
+        let stream = quote! {u_y - c^2 * u_xx + u^-0.5 = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert_eq!(
+            parse_pde(expr, &Dimensions::default()).unwrap(),
+            Expression::Sum(vec![
+                Expression::Derivative(Variable::Y, 1),
+                Expression::Negate(Box::new(Expression::Prod(vec![
+                    Expression::Pow(
+                        Box::new(Expression::SymbolicConstant(format_ident!("c"))),
+                        2.
+                    ),
+                    Expression::Derivative(Variable::X, 2),
+                ]))),
+                Expression::Pow(Box::new(Expression::SolutionVal), -0.5),
+            ])
+        );
+    }
+
+    #[test]
+    fn negation_and_integers() {
+        let stream = quote! {-u_y + -nu * u_xx - 2 * u = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert_eq!(
+            parse_pde(expr, &Dimensions::default()).unwrap(),
+            Expression::Sum(vec![
+                Expression::Negate(Box::new(Expression::Derivative(Variable::Y, 1))),
+                Expression::Prod(vec![
+                    Expression::Negate(Box::new(Expression::SymbolicConstant(format_ident!("nu")))),
+                    Expression::Derivative(Variable::X, 2),
+                ]),
+                Expression::Negate(Box::new(Expression::Prod(vec![
+                    Expression::Constant(2.),
+                    Expression::SolutionVal,
+                ]))),
+            ])
+        );
+    }
+
+    #[test]
+    fn logical_not() {
+        let stream = quote! {u_y + !u = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        let err = parse_pde(expr, &Dimensions::default()).unwrap_err();
+        assert!(err.to_string().contains("Logical negation"));
+    }
+
+    #[test]
+    fn leibniz_notation() {
+        let stream = quote! {du/dy - nu * d2u/dx2 + d2u/dxdy = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert_eq!(
+            parse_pde(expr, &Dimensions::default()).unwrap(),
+            Expression::Sum(vec![
+                Expression::Derivative(Variable::Y, 1),
+                Expression::Negate(Box::new(Expression::Prod(vec![
+                    Expression::SymbolicConstant(format_ident!("nu")),
+                    Expression::Derivative(Variable::X, 2),
+                ]))),
+                Expression::CrossDerivative(vec![Variable::X, Variable::Y]),
+            ])
+        );
+    }
+
+    #[test]
+    fn leibniz_order_mismatch() {
+        let stream = quote! {d2u/dx + u = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert!(parse_pde(expr, &Dimensions::default()).is_err());
+    }
+
+    #[test]
+    fn nonzero_rhs() {
+        let stream = quote! {u_xx + u_yy = f - 2 * u};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert_eq!(
+            parse_pde(expr, &Dimensions::default()).unwrap(),
+            Expression::Sum(vec![
+                Expression::Derivative(Variable::X, 2),
+                Expression::Derivative(Variable::Y, 2),
+                Expression::Negate(Box::new(Expression::SymbolicConstant(format_ident!("f")))),
+                Expression::Negate(Box::new(Expression::Negate(Box::new(Expression::Prod(
+                    vec![Expression::Constant(2.), Expression::SolutionVal]
+                ))))),
+            ])
+        );
+    }
+
+    #[test]
+    fn non_literal_exponent() {
+        let stream = quote! {u_y + u^c = 0};
+        let expr: Expr = syn::parse2(stream).unwrap();
+
+        assert!(parse_pde(expr, &Dimensions::default()).is_err());
+    }
+
+    mod properties {
+        use discreet_common::algebra::Expression;
+        use proptest::prelude::*;
+        use syn::{BinOp, Expr, Lit, UnOp};
+
+        use crate::{args::Dimensions, diff_eq::parse_pde};
+
+        fn symbol_value(name: &str) -> f64 {
+            match name {
+                "a" => 1.5,
+                "b" => -0.5,
+                "u" => 0.8,
+                other => panic!("Unexpected symbol {other}"),
+            }
+        }
+
+        /// Evaluates the expression the way Rust would, to compare against the parsed equation.
+        fn reference_value(expr: &Expr) -> f64 {
+            match expr {
+                Expr::Binary(b) => {
+                    let left = reference_value(&b.left);
+                    let right = reference_value(&b.right);
+                    match b.op {
+                        BinOp::Add(_) => left + right,
+                        BinOp::Sub(_) => left - right,
+                        BinOp::Mul(_) => left * right,
+                        BinOp::Div(_) => left / right,
+                        op => panic!("Unexpected operator {op:?}"),
+                    }
+                }
+                Expr::Unary(u) => match u.op {
+                    UnOp::Neg(_) => -reference_value(&u.expr),
+                    op => panic!("Unexpected operator {op:?}"),
+                },
+                Expr::Paren(p) => reference_value(&p.expr),
+                Expr::Lit(l) => match &l.lit {
+                    Lit::Int(i) => i.base10_parse().unwrap(),
+                    Lit::Float(f) => f.base10_parse().unwrap(),
+                    other => panic!("Unexpected literal {other:?}"),
+                },
+                Expr::Path(p) => symbol_value(&p.path.get_ident().unwrap().to_string()),
+                other => panic!("Unexpected expression {other:?}"),
             }
-            Err(e) => {
-                panic!()
+        }
+
+        fn equation_side() -> impl Strategy<Value = String> {
+            let leaf = prop_oneof![
+                Just("a".to_string()),
+                Just("b".to_string()),
+                Just("u".to_string()),
+                (1u8..10).prop_map(|i| i.to_string()),
+                (1u8..10).prop_map(|i| format!("{i}.25")),
+            ];
+
+            leaf.prop_recursive(4, 32, 2, |inner| {
+                prop_oneof![
+                    (
+                        inner.clone(),
+                        prop::sample::select(vec!["+", "-", "*", "/"]),
+                        inner.clone()
+                    )
+                        .prop_map(|(l, op, r)| format!("{l} {op} {r}")),
+                    inner.clone().prop_map(|e| format!("({e})")),
+                    inner.prop_map(|e| format!("-{e}")),
+                ]
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn parsed_equation_matches_rust(lhs in equation_side(), rhs in equation_side()) {
+                let lhs_expr: Expr = syn::parse_str(&lhs).unwrap();
+                let rhs_expr: Expr = syn::parse_str(&rhs).unwrap();
+                let expected = reference_value(&lhs_expr) - reference_value(&rhs_expr);
+
+                let equation: Expr = syn::parse_str(&format!("{lhs} = {rhs}")).unwrap();
+                let parsed = parse_pde(equation, &Dimensions::default()).unwrap();
+
+                let value = parsed.evaluate(&|e| match e {
+                    Expression::SolutionVal => symbol_value("u"),
+                    Expression::SymbolicConstant(c) => symbol_value(&c.to_string()),
+                    other => panic!("Unexpected leaf {other:?}"),
+                });
+
+                if expected.is_finite() && expected.abs() < 1e6 {
+                    prop_assert!(
+                        (value - expected).abs() <= 1e-9 * (1. + expected.abs()),
+                        "{lhs} = {rhs} evaluated to {value} instead of {expected}"
+                    );
+                }
             }
         }
     }
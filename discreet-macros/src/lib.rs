@@ -3,78 +3,186 @@ use std::collections::HashMap;
 
 use discreet_common::{
     algebra::{MeshExpr, Variable},
-    taylor::TaylorTable,
+    taylor::{TaylorTable, TaylorTableND},
 };
 use proc_macro::TokenStream;
-use proc_macro2::Span;
-use quote::quote;
-use syn::{parse_macro_input, spanned::Spanned};
+use quote::{format_ident, quote};
+use syn::parse_macro_input;
 
 mod args;
+mod codegen;
+mod codegen1d;
+mod codegen3d;
 mod diff_eq;
+mod lines;
 
-use args::{CommaSeparatedArgs, ident_list, parse_stencil};
-
-use crate::diff_eq::parse_pde;
+use args::{BOUNDARIES_1D, BOUNDARIES_2D, CommaSeparatedArgs, FACES_3D, SchemeArgs};
 
 /// Generates a struct implementing the finite difference method in 2D.
 /// The name of this struct is `FiniteDiff`.
-/// The code can be used by calling `FiniteDiff::compute(&mut mesh)`, where `mesh` is a `FiniteDiffMesh1D`.
+/// The code can be used by calling `FiniteDiff::new(consts, mesh, fns)`, where `mesh` is a `FiniteDiffMesh`,
+/// and then `run_iteration`.
+///
+/// For steady problems, `FiniteDiff::solve(tolerance, max_iters, method)` repeatedly sweeps the mesh with
+/// Jacobi, Gauss-Seidel or SOR (see `discreet_common::iterative`) until the largest residual is below the
+/// tolerance, and returns a `ConvergenceReport` with the residual history. Explicit schemes update one node at
+/// a time, and implicit schemes (see `unknown`) update a whole row at a time, which is line relaxation.
+///
+/// With the `time` argument, the mesh covers space only and the equation is integrated in time with the
+/// method of lines instead: only the spatial derivatives are discretised, which gives a system of ODEs
+/// `du/dt = rhs(u)` for the values at the nodes. `FiniteDiff` then has `rhs(&self, u, du)`, and
+/// `integrate(end, dt, scheme, snapshot_times)`, which advances the solution held in the mesh from the current
+/// time to `end` with one of the explicit Runge-Kutta schemes of `discreet_common::timestepping::TimeScheme`,
+/// and returns the solution at each of `snapshot_times` it passes. The initial condition is the values in the
+/// mesh passed to `FiniteDiff::new`, and the boundary conditions are applied at the time of each stage.
+/// `integrate_adaptive(end, control, snapshot_times)` instead chooses the step size with an embedded
+/// Runge-Kutta pair to keep the estimated error within the tolerance of `control`, a
+/// `discreet_common::timestepping::StepControl`, and reports the rejected steps and the size of each step.
 ///
 /// # Arguments:
+/// `dimensions`: The names of the independent variables, corresponding to the first and second index of
+/// the mesh respectively. These are used for derivatives in the equation. Defaults to `(x, y)`.
+/// Example (space and time): `dimensions: (x, t)`, so that the equation can use `u_t` and `u_xx`.
+///
+/// `time`: The name of time, for integrating the equation in time with the method of lines. The equation
+/// should be linear in `u_t`, and can't have other derivatives w.r.t. time. Boundary values can use the time,
+/// e.g. `boundaries: { left: dirichlet(t.sin()) }`, but functions don't depend on it. Example (heat
+/// equation): `time: t`, `equation: u_t = nu * (u_xx + u_yy)`,
+/// `stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)]`.
+///
+/// `max_dt`: With `time`, the longest stable time step, e.g. from the CFL condition, which adaptive
+/// integration never exceeds. This is an expression of the constants and the smallest spacing of the mesh
+/// along each index, `dx` and `dy`. Example (advection-diffusion):
+/// `max_dt: (dx / c).min(0.5 * dx * dx / nu)`.
+///
 /// `constants`: Any constants used in the differential equation. This will be turned into `struct Constants`,
 /// which will be a parameter to `FiniteDiff::new`. Example (linear diffusion equation): `constants: [nu]`.
+/// Constants can be given default values, e.g. `constants: [c, nu = 0.1]`. `Constants::new` takes the
+/// constants without a default value as arguments, and if every constant has a default, `Constants`
+/// implements `Default`.
 ///
 /// `functions`: Functions used in the PDE that aren't the PDE being solved for. For example, if you want to
 /// solve `u_x + u_y - f(x, y) = 0`, you would use `functions: [f]`, and `equation: u_x + u_y - f = 0`.
 /// The given functions will be used to generate a `struct FunctionValueMesh`, which represents the function
 /// values at each point in the computational domain. This can be filled in at runtime before running the
 /// finite difference scheme by using the new function of `FunctionValueMesh` with closures corresponding
-/// to the functions (in the physical domain).
+/// to the functions (in the physical domain), e.g. `FunctionValueMesh::new(&mesh, |x, y| x * y)`.
 ///
 /// `equation`: The equation to solve. This should be in the form `L(u) = 0`, where L is a finite difference
-/// operator. Example (linear diffusion): `equation: u_y - nu * u_xx = 0`.
+/// operator, or `L(u) = R`, which is treated as `L(u) - R = 0`. Examples (linear diffusion, Poisson):
+/// `equation: u_t - nu * u_xx = 0`, `equation: u_xx + u_yy = f`.
 ///
 /// `stencil`: The nodes to be used for calculating the next unknown. Coordinates are relative to the center
 /// of the Taylor expansions. Example (explicit in time, central difference in space):
 /// `stencil: [(-1, 0), (0, 0), (1, 0)]`
+///
+/// `boundaries`: The conditions on each boundary of the mesh, which `FiniteDiff::new` sets on the mesh. Each
+/// boundary (`left`, `right`, `bottom` or `top`, where left and bottom are where the first and second index
+/// are zero) can have `dirichlet(value)`, `neumann(value)` or `robin(a, b, value)` for `a u + b du/dn = value`,
+/// with `n` the outward normal. Values can use the constants, and Dirichlet values can also use the
+/// coordinates. Example (heat equation in a rod with an insulated end):
+/// `boundaries: { bottom: dirichlet(x.sin()), left: dirichlet(0.0), right: neumann(0.0) }`.
+///
+/// `unknown`: The nodes of the stencil whose values are found by the scheme. Defaults to `(0, 0)`, which gives
+/// an explicit scheme. Several nodes on the same row make the scheme implicit: the equations centered on each
+/// node of the row are solved together as a banded system, which is tridiagonal for three unknowns. Example
+/// (backward Euler for the diffusion equation): `stencil: [(-1, 0), (0, 0), (1, 0), (0, -1)]`,
+/// `unknown: [(-1, 0), (0, 0), (1, 0)]`. Can't be used with `time`.
+///
+/// Neumann and Robin conditions set on the mesh with `FiniteDiffMesh::set_boundary_condition` are reapplied
+/// after each row of a marching scheme, and at the end of `run_iteration` and of each sweep of `solve`.
+/// Implicit schemes solve for the boundary nodes of each row along with the rest of the row.
+/// Along an index made periodic with `FiniteDiffMesh::set_periodic`, the stencil wraps around the mesh, so
+/// the scheme is applied at every node, including those on the boundaries. When the rows of an implicit scheme
+/// are periodic, each row is solved as a cyclic banded system.
+///
+/// The same scheme runs on uniform meshes, on rectilinear meshes with uneven spacing built with
+/// `FiniteDiffMesh::from_coordinates`, and on curvilinear meshes built with
+/// `FiniteDiffMesh::from_physical_domain`. On a rectilinear mesh, the weights of the nodes in each derivative
+/// are found from the spacing around each node, so clustering the nodes keeps the order of accuracy. Mixed
+/// derivatives there need the stencil to hold every combination of the nodes on each axis.
+///
+/// On a curvilinear mesh, derivatives are found with the chain rule from derivatives along the mesh lines
+/// and the metric terms stored in the mesh, which needs derivatives of at most second order, and a stencil
+/// that can approximate the first and second derivatives along both indices (and the mixed one, for second
+/// derivatives). For example, the five point Laplacian has no mixed derivative, so it only gives schemes for
+/// uniform and rectilinear meshes.
+///
+/// `FiniteDiff::new` panics when given a kind of mesh that the stencil can't approximate the derivatives on,
+/// naming the derivatives that are missing.
 #[proc_macro]
 pub fn finite_diff_2d(args: TokenStream) -> TokenStream {
     let parsed = parse_macro_input!(args as CommaSeparatedArgs);
-
-    // parsed.print_all();
-    let expr = parsed.find_arg("equation".to_string()).unwrap();
-    let eqn_span = expr.span();
-
-    // eprintln!("{expr:#?}");
-
-    let eqn = match parse_pde(expr) {
-        Ok(e) => e,
+    let SchemeArgs {
+        dims,
+        time,
+        max_dt,
+        equation: eqn,
+        equation_span: eqn_span,
+        stencil,
+        stencil_span,
+        unknowns,
+        unknowns_span,
+        constants,
+        functions,
+        boundaries,
+    } = match SchemeArgs::parse(&parsed, 2, BOUNDARIES_2D) {
+        Ok(args) => args,
         Err(e) => return e.to_compile_error().into(),
     };
-    // println!("{eqn:#?}");
+    let eqn_dims = time.as_ref().map_or(&dims, |(d, _)| d);
 
-    let stencil = parsed.find_arg("stencil".to_string()).unwrap();
-    let stencil_span = stencil.span();
-    let stencil = match parse_stencil(stencil) {
-        Ok(e) => e,
-        Err(e) => return e.to_compile_error().into(),
-    };
+    let stencil: Vec<(isize, isize)> = stencil.into_iter().map(|o| (o[0], o[1])).collect();
+    let unknowns: Vec<(isize, isize)> = unknowns.into_iter().map(|o| (o[0], o[1])).collect();
 
     let required_derivatives = eqn.list_required_derivatives();
 
     let x_taylor_table = TaylorTable::new(stencil.as_slice(), Variable::X);
     let y_taylor_table = TaylorTable::new(stencil.as_slice(), Variable::Y);
+    let taylor_table_2d = TaylorTableND::new(stencil.as_slice());
+
+    // Derivatives w.r.t. a single variable use the nodes on that axis where possible, and mixed
+    // derivatives need the whole stencil.
+    let computational_derivative = |vars: &[Variable]| {
+        match vars {
+            [Variable::X, ..] if vars.iter().all(|v| *v == Variable::X) => {
+                x_taylor_table.get_scheme(vars.len())
+            }
+            [Variable::Y, ..] if vars.iter().all(|v| *v == Variable::Y) => {
+                y_taylor_table.get_scheme(vars.len())
+            }
+            _ => None,
+        }
+        .or_else(|| taylor_table_2d.get_scheme(vars))
+    };
 
     let mut derivatives = HashMap::new();
+    let mut rectilinear_derivatives = HashMap::new();
+    // On curvilinear meshes, derivatives are found with the chain rule. The derivatives that can't be found
+    // on a kind of mesh are kept to report when the scheme is used on one.
+    let mut curvilinear_derivatives = HashMap::new();
+    let (mut rectilinear_missing, mut curvilinear_missing) = (Vec::new(), Vec::new());
+
+    for vars in required_derivatives {
+        if let Some((_, time)) = time.as_ref().filter(|(_, t)| vars.contains(t)) {
+            let derivative = match lines::discretise_time_derivative(&vars, *time) {
+                Ok(d) => d,
+                Err(e) => {
+                    return syn::Error::new(eqn_span, e.as_str())
+                        .to_compile_error()
+                        .into();
+                }
+            };
 
-    for (v, o) in required_derivatives {
-        let derivative = match v {
-            Variable::X => x_taylor_table.get_scheme(o),
-            Variable::Y => y_taylor_table.get_scheme(o),
-        };
+            // The same on every kind of mesh
+            for map in [&mut rectilinear_derivatives, &mut curvilinear_derivatives] {
+                map.insert(vars.clone(), derivative.clone());
+            }
+            derivatives.insert(vars, derivative);
+            continue;
+        }
 
-        let derivative = match derivative {
+        let derivative = match computational_derivative(&vars) {
             Some(d) => d,
             None => {
                 return syn::Error::new(
@@ -86,31 +194,264 @@ pub fn finite_diff_2d(args: TokenStream) -> TokenStream {
             }
         };
 
-        derivatives.insert((v, o), derivative);
+        match nonuniform_derivative(&vars, &stencil) {
+            Some(d) => {
+                rectilinear_derivatives.insert(vars.clone(), d);
+            }
+            None => rectilinear_missing.push(eqn_dims.derivative_name(&vars)),
+        }
+
+        match chain_rule(&vars, computational_derivative) {
+            Some(d) => {
+                curvilinear_derivatives.insert(vars.clone(), d);
+            }
+            None => curvilinear_missing.push(eqn_dims.derivative_name(&vars)),
+        }
+
+        let derivative = scale_derivative(derivative, &vars);
+        derivatives.insert(vars, derivative);
     }
 
     // println!("{derivatives:#?}");
 
-    let constants = match parsed.find_arg("constants".to_string()) {
-        Some(constants) => match ident_list(constants) {
-            Ok(c) => c,
-            Err(e) => return e.to_compile_error().into(),
-        },
-        None => vec![],
+    // println!("{constants:?}");
+    // println!("{functions:?}");
+
+    let fn_strings: Vec<String> = functions.iter().map(|f| format!("{f}")).collect();
+    let discretised_de =
+        match MeshExpr::from_diff_eq(eqn.clone(), fn_strings.as_slice(), &derivatives, 2) {
+            Ok(e) => e,
+            Err(e) => {
+                return syn::Error::new(eqn_span, e.as_str())
+                    .to_compile_error()
+                    .into();
+            }
+        };
+    let domain_residual = |domain: codegen::Domain, derivatives, missing: Vec<String>| {
+        if missing.is_empty() {
+            MeshExpr::from_diff_eq(eqn.clone(), fn_strings.as_slice(), &derivatives, 2)
+        } else {
+            Err(domain.unsupported(&missing))
+        }
     };
-    let functions = match parsed.find_arg("functions".to_string()) {
-        Some(functions) => match ident_list(functions) {
-            Ok(c) => c,
-            Err(e) => return e.to_compile_error().into(),
+
+    let residuals = vec![
+        (codegen::Domain::Simple, Ok(discretised_de)),
+        (
+            codegen::Domain::Rectilinear,
+            domain_residual(
+                codegen::Domain::Rectilinear,
+                rectilinear_derivatives,
+                rectilinear_missing,
+            ),
+        ),
+        (
+            codegen::Domain::Complex,
+            domain_residual(
+                codegen::Domain::Complex,
+                curvilinear_derivatives,
+                curvilinear_missing,
+            ),
+        ),
+    ];
+    let reject_unsupported = codegen::reject_unsupported(&residuals);
+
+    let const_names: Vec<_> = constants.iter().map(|(c, _)| c).collect();
+    let boundary_setup = codegen::boundary_setup(
+        &boundaries,
+        &dims,
+        &const_names,
+        quote!(::discreet_common::mesh2d::Boundary),
+    );
+    let shared_items = constants_and_functions(&constants, &functions, quote!(FiniteDiffMesh), 2);
+
+    if time.is_some() {
+        let mut rates = Vec::with_capacity(residuals.len());
+        for (domain, residual) in residuals {
+            let rate = match residual {
+                Ok(residual) => match lines::rate_of_change(&residual) {
+                    Ok(rate) => Ok(rate),
+                    Err(e) => {
+                        return syn::Error::new(eqn_span, e.as_str())
+                            .to_compile_error()
+                            .into();
+                    }
+                },
+                Err(reason) => Err(reason),
+            };
+            rates.push((domain, rate));
+        }
+
+        let max_dt = max_dt.map(|limit| {
+            lines::max_dt_body(
+                &limit,
+                quote!(let [dx, dy] = self.mesh.min_spacing();),
+                &const_names,
+            )
+        });
+        let integrator = lines::integrator(
+            quote!(FiniteDiffMesh),
+            eqn_dims.idents().last().unwrap(),
+            reject_unsupported,
+            boundary_setup,
+            codegen::rhs_body(&rates, &stencil),
+            max_dt,
+        );
+
+        return quote!(
+            #integrator
+
+            #shared_items
+        )
+        .into();
+    }
+
+    let error_stats = codegen::error_stats(&residuals, &stencil);
+
+    let scheme = match unknowns.as_slice() {
+        [unknown] => codegen::explicit_scheme(&residuals, *unknown, &stencil),
+        _ => match codegen::implicit_scheme(&residuals, &unknowns, &stencil) {
+            Ok(scheme) => scheme,
+            Err(e) => {
+                return syn::Error::new(unknowns_span, e.as_str())
+                    .to_compile_error()
+                    .into();
+            }
         },
-        None => vec![],
     };
+    let iteration = scheme.iteration;
+    let scheme_methods = scheme.methods;
 
-    // println!("{constants:?}");
-    // println!("{functions:?}");
+    quote!(
+        struct FiniteDiff {
+            consts: Constants,
+            fns: FunctionValueMesh,
+            mesh: FiniteDiffMesh
+        }
+
+        impl FiniteDiff {
+            fn new(consts: Constants, mut mesh: FiniteDiffMesh, fns: FunctionValueMesh) -> Self {
+                #reject_unsupported
+                Self::set_boundaries(&mut mesh, consts);
+
+                Self {
+                    consts,
+                    mesh,
+                    fns
+                }
+            }
+
+            /// Sets the boundary conditions given in the problem definition on the mesh.
+            #[allow(unused_variables)]
+            fn set_boundaries(mesh: &mut FiniteDiffMesh, consts: Constants) {
+                #boundary_setup
+            }
+
+            #[allow(unused_variables)]
+            fn run_iteration(&mut self) {
+                #iteration
+
+                self.mesh.apply_boundary_conditions();
+            }
+
+            #error_stats
+
+            #scheme_methods
+        }
+
+        #shared_items
+    )
+    .into()
+}
+
+/// Generates a struct implementing an explicit finite difference method on a 3D mesh. This works like
+/// [`finite_diff_2d!`], with the struct `FiniteDiff` holding a `FiniteDiffMesh3D` instead, and three independent
+/// variables. One of these can be time, which gives transient problems in 2D.
+///
+/// # Arguments:
+/// `dimensions`: The names of the independent variables, corresponding to the three indices of the mesh.
+/// Defaults to `(x, y, z)`. Example (transient heat conduction): `dimensions: (x, y, t)`, so that the
+/// equation can be `u_t - alpha * (u_xx + u_yy) = 0`.
+///
+/// `constants`, `functions` and `equation`: As for `finite_diff_2d!`. Functions are of the three coordinates.
+///
+/// `stencil`: The nodes used by the scheme, given as offsets along the three indices. Example (explicit in
+/// time, central differences in space):
+/// `stencil: [(0, 0, 0), (-1, 0, -1), (0, 0, -1), (1, 0, -1), (0, -1, -1), (0, 1, -1)]`.
+///
+/// `unknown`: The node of the stencil whose value is found by the scheme. Defaults to `(0, 0, 0)`. Only
+/// explicit schemes are supported, so this is a single node.
+///
+/// `boundaries`: Dirichlet conditions on the faces of the mesh (`left`, `right`, `bottom`, `top`, `back` or
+/// `front`, where left, bottom and back are where the first, second and third index are zero), which can use
+/// the constants and the coordinates. When the third variable is time, the condition on `back` is the
+/// initial condition. Example: `boundaries: { back: dirichlet(x.sin() * y.sin()), left: dirichlet(0.0) }`.
+///
+/// Layers of constant third index are computed in turn by `run_iteration`, and the conditions on the faces
+/// are reapplied to each layer once it has been computed. Steady problems can also be iterated to
+/// convergence with `FiniteDiff::solve(tolerance, max_iters, method)`.
+#[proc_macro]
+pub fn finite_diff_3d(args: TokenStream) -> TokenStream {
+    let parsed = parse_macro_input!(args as CommaSeparatedArgs);
+    // `time` is rejected when parsing, as there are no 3D meshes for the method of lines
+    let SchemeArgs {
+        dims,
+        equation: eqn,
+        equation_span: eqn_span,
+        stencil,
+        stencil_span,
+        unknowns,
+        unknowns_span,
+        constants,
+        functions,
+        boundaries,
+        ..
+    } = match SchemeArgs::parse(&parsed, 3, FACES_3D) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let unknown = match unknowns.as_slice() {
+        [unknown] => unknown.clone(),
+        _ => {
+            return syn::Error::new(
+                unknowns_span,
+                "3D schemes are explicit, so they have a single unknown, e.g. `(0, 0, 0)`.",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let taylor_tables = [Variable::X, Variable::Y, Variable::Z]
+        .map(|variable| TaylorTable::new(stencil.as_slice(), variable));
+    let taylor_table_3d = TaylorTableND::new(stencil.as_slice());
+
+    let mut derivatives = HashMap::new();
+    for vars in eqn.list_required_derivatives() {
+        // Derivatives w.r.t. a single variable use the nodes on that axis where possible
+        let along_axis = match vars.first() {
+            Some(&first) if vars.iter().all(|v| *v == first) => {
+                taylor_tables[first.index()].get_scheme(vars.len())
+            }
+            _ => None,
+        };
+
+        let Some(derivative) = along_axis.or_else(|| taylor_table_3d.get_scheme(&vars)) else {
+            return syn::Error::new(
+                stencil_span,
+                "Could not construct discretisation of derivative with this stencil.",
+            )
+            .to_compile_error()
+            .into();
+        };
+
+        let derivative = scale_derivative(derivative, &vars);
+        derivatives.insert(vars, derivative);
+    }
 
     let fn_strings: Vec<String> = functions.iter().map(|f| format!("{f}")).collect();
-    let discretised_de = match MeshExpr::from_diff_eq(eqn, fn_strings.as_slice(), &derivatives) {
+    let residual = match MeshExpr::from_diff_eq(eqn, fn_strings.as_slice(), &derivatives, 3) {
         Ok(e) => e,
         Err(e) => {
             return syn::Error::new(eqn_span, e.as_str())
@@ -119,31 +460,30 @@ pub fn finite_diff_2d(args: TokenStream) -> TokenStream {
         }
     };
 
-    let error_expr = discretised_de.render();
-
-    let rhs_expr = discretised_de.find_root_linear(&MeshExpr::AtOffset(0, 0));
-
-    todo!("Insert mesh scaling factors");
+    let error_stats = codegen3d::error_stats(&residual, &stencil);
+    let codegen::Scheme {
+        iteration,
+        methods: scheme_methods,
+    } = codegen3d::explicit_scheme(&residual, &unknown, &stencil);
 
-    let rhs_expr = rhs_expr.render();
-
-    let mut consts = quote!();
-    for c in constants {
-        consts = quote!(#consts pub c: f64,);
-    }
-
-    // PLACEHOLDER
-    let functions = quote!();
+    let const_names: Vec<_> = constants.iter().map(|(c, _)| c).collect();
+    let boundary_setup = match codegen3d::boundary_setup(&boundaries, &dims, &const_names) {
+        Ok(b) => b,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let shared_items = constants_and_functions(&constants, &functions, quote!(FiniteDiffMesh3D), 3);
 
     quote!(
         struct FiniteDiff {
             consts: Constants,
             fns: FunctionValueMesh,
-            mesh: FiniteDiffMesh
+            mesh: FiniteDiffMesh3D
         }
 
         impl FiniteDiff {
-            fn new(consts: Constants, mesh: FiniteDiffMesh, fns: FunctionValueMesh) -> Self {
+            fn new(consts: Constants, mut mesh: FiniteDiffMesh3D, fns: FunctionValueMesh) -> Self {
+                Self::set_boundaries(&mut mesh, consts);
+
                 Self {
                     consts,
                     mesh,
@@ -151,75 +491,415 @@ pub fn finite_diff_2d(args: TokenStream) -> TokenStream {
                 }
             }
 
+            /// Sets the boundary conditions given in the problem definition on the mesh.
+            #[allow(unused_variables)]
+            fn set_boundaries(mesh: &mut FiniteDiffMesh3D, consts: Constants) {
+                #boundary_setup
+            }
+
+            #[allow(unused_variables)]
             fn run_iteration(&mut self) {
-                let indices = self.mesh.index_iter().filter(|(i, j)| *i > 0 && *j > 0);
+                #iteration
 
-                match self.mesh.get_scaling() {
-                    MeshScaling::SimpleGrid(dx, dy) => {
-                        for (i, j) in indices {
-                            self.iterate_point_simple_domain(i, j);
-                        }
-                    }
-                    MeshScaling::ComplexPhysDomain(factors) => {
-                        todo!()
-                    }
-                }
+                self.mesh.apply_boundary_conditions();
             }
 
-            fn get_error_stats(&self) -> (f64, f64) {
-                let mut prev_elements = 0.;
-                let mut mean = 0.;
-                let mut max = 0.;
+            #error_stats
 
-                let indices = self.mesh.index_iter().filter(|(i, j)| *i > 0 && *j > 0);
+            #scheme_methods
+        }
 
-                match self.mesh.get_scaling() {
-                    MeshScaling::SimpleGrid(dx, dy) => {
+        #shared_items
+    )
+    .into()
+}
 
-                        for (i, j) in indices {
-                            let error = (#error_expr).abs();
+/// Generates a struct implementing the finite difference method on a 1D mesh, for ODEs and two-point boundary
+/// value problems. This works like [`finite_diff_2d!`], with the struct `FiniteDiff` holding a
+/// `FiniteDiffMesh1D` instead.
+///
+/// # Arguments:
+/// `dimensions`: The name of the independent variable, e.g. `(t)` for an initial value problem. Defaults to
+/// `(x)`.
+///
+/// `time`: As for `finite_diff_2d!`, integrates the equation in time with the method of lines, so that the
+/// mesh only covers space. Example (heat equation in a rod): `time: t`, `equation: u_t = nu * u_xx`,
+/// `stencil: [-1, 0, 1]`, `boundaries: { left: dirichlet(0.0), right: dirichlet(0.0) }`.
+///
+/// `max_dt`: As for `finite_diff_2d!`, the longest stable time step, as an expression of the constants and
+/// the spacing `dx`.
+///
+/// `constants`, `functions` and `equation`: As for `finite_diff_2d!`. Functions are of the coordinate.
+///
+/// `stencil`: The offsets of the nodes used by the scheme. Example (central differences): `stencil: [-1, 0, 1]`.
+///
+/// `boundaries`: The conditions on the `left` and `right` ends of the mesh, which can be `dirichlet(value)`,
+/// `neumann(value)` or `robin(a, b, value)` as in 2D.
+///
+/// `unknown`: The nodes of the stencil whose values are found by the scheme. Defaults to `0`, which gives an
+/// explicit scheme. `run_iteration` then marches through the mesh, which solves initial value problems: the
+/// nodes before the first center of the stencil keep their values, which are the initial conditions.
+/// Boundary value problems can also be solved with an explicit scheme by iterating with
+/// `FiniteDiff::solve(tolerance, max_iters, method)`, which implicit schemes have too. Can't be used with
+/// `time`.
+///
+/// Several unknowns make the scheme implicit: the equations centered on every node are solved together with
+/// the conditions at the ends as a banded system, which solves a linear boundary value problem in one
+/// `run_iteration`. With `stencil: [-1, 0, 1]`, `unknown: [-1, 0, 1]` and Dirichlet conditions, the system is
+/// tridiagonal. Example (`u'' = f` with an insulated end):
+/// `equation: u_xx = f`, `boundaries: { left: dirichlet(0.0), right: neumann(0.0) }`.
+#[proc_macro]
+pub fn finite_diff_1d(args: TokenStream) -> TokenStream {
+    let parsed = parse_macro_input!(args as CommaSeparatedArgs);
+    let SchemeArgs {
+        dims,
+        time,
+        max_dt,
+        equation: eqn,
+        equation_span: eqn_span,
+        stencil,
+        stencil_span,
+        unknowns,
+        unknowns_span,
+        constants,
+        functions,
+        boundaries,
+    } = match SchemeArgs::parse(&parsed, 1, BOUNDARIES_1D) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let eqn_dims = time.as_ref().map_or(&dims, |(d, _)| d);
 
-                            let total = mean * prev_elements + error;
-                            prev_elements += 1.;
-                            mean = total / prev_elements;
+    let taylor_table = TaylorTable::new(stencil.as_slice(), Variable::X);
 
-                            if error > max {
-                                max = error;
-                            }
-                        }
-                    }
-                    MeshScaling::ComplexPhysDomain(factors) => {
-                        todo!()
-                    }
+    let mut derivatives = HashMap::new();
+    for vars in eqn.list_required_derivatives() {
+        if let Some((_, time)) = time.as_ref().filter(|(_, t)| vars.contains(t)) {
+            match lines::discretise_time_derivative(&vars, *time) {
+                Ok(d) => derivatives.insert(vars, d),
+                Err(e) => {
+                    return syn::Error::new(eqn_span, e.as_str())
+                        .to_compile_error()
+                        .into();
                 }
+            };
+            continue;
+        }
+
+        let Some(derivative) = taylor_table.get_scheme(vars.len()) else {
+            return syn::Error::new(
+                stencil_span,
+                "Could not construct discretisation of derivative with this stencil.",
+            )
+            .to_compile_error()
+            .into();
+        };
+
+        let derivative = scale_derivative(derivative, &vars);
+        derivatives.insert(vars, derivative);
+    }
+
+    let fn_strings: Vec<String> = functions.iter().map(|f| format!("{f}")).collect();
+    let residual = match MeshExpr::from_diff_eq(eqn, fn_strings.as_slice(), &derivatives, 1) {
+        Ok(e) => e,
+        Err(e) => {
+            return syn::Error::new(eqn_span, e.as_str())
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let stencil: Vec<isize> = stencil.into_iter().map(|o| o[0]).collect();
+    let unknowns: Vec<isize> = unknowns.into_iter().map(|o| o[0]).collect();
+
+    let const_names: Vec<_> = constants.iter().map(|(c, _)| c).collect();
+    let boundary_setup = codegen::boundary_setup(
+        &boundaries,
+        &dims,
+        &const_names,
+        quote!(::discreet_common::mesh1d::Boundary),
+    );
+    let shared_items = constants_and_functions(&constants, &functions, quote!(FiniteDiffMesh1D), 1);
+
+    if time.is_some() {
+        let rate = match lines::rate_of_change(&residual) {
+            Ok(r) => r,
+            Err(e) => {
+                return syn::Error::new(eqn_span, e.as_str())
+                    .to_compile_error()
+                    .into();
+            }
+        };
+        let max_dt = max_dt.map(|limit| {
+            lines::max_dt_body(&limit, quote!(let dx = self.mesh.spacing();), &const_names)
+        });
+        let integrator = lines::integrator(
+            quote!(FiniteDiffMesh1D),
+            eqn_dims.idents().last().unwrap(),
+            quote!(),
+            boundary_setup,
+            codegen1d::rhs_body(&rate, &stencil),
+            max_dt,
+        );
+
+        return quote!(
+            #integrator
+
+            #shared_items
+        )
+        .into();
+    }
 
-                (mean, max)
+    let error_stats = codegen1d::error_stats(&residual, &stencil);
+    let scheme = match unknowns.as_slice() {
+        &[unknown] => codegen1d::explicit_scheme(&residual, unknown, &stencil),
+        _ => match codegen1d::implicit_scheme(&residual, &unknowns, &stencil) {
+            Ok(scheme) => scheme,
+            Err(e) => {
+                return syn::Error::new(unknowns_span, e.as_str())
+                    .to_compile_error()
+                    .into();
             }
+        },
+    };
+    let iteration = scheme.iteration;
+    let scheme_methods = scheme.methods;
+
+    quote!(
+        struct FiniteDiff {
+            consts: Constants,
+            fns: FunctionValueMesh,
+            mesh: FiniteDiffMesh1D
+        }
 
-            fn iterate_point_simple_domain(
-                &mut self,
-                i: usize,
-                j: usize,
-            ) {
-                let v = #rhs_expr;
+        impl FiniteDiff {
+            fn new(consts: Constants, mut mesh: FiniteDiffMesh1D, fns: FunctionValueMesh) -> Self {
+                Self::set_boundaries(&mut mesh, consts);
 
-                self.mesh.set_at(i, j, v);
+                Self {
+                    consts,
+                    mesh,
+                    fns
+                }
+            }
+
+            /// Sets the boundary conditions given in the problem definition on the mesh.
+            #[allow(unused_variables)]
+            fn set_boundaries(mesh: &mut FiniteDiffMesh1D, consts: Constants) {
+                #boundary_setup
+            }
+
+            #[allow(unused_variables)]
+            fn run_iteration(&mut self) {
+                #iteration
+
+                self.mesh.apply_boundary_conditions();
             }
+
+            #error_stats
+
+            #scheme_methods
         }
 
+        #shared_items
+    )
+    .into()
+}
+
+/// The `Constants` struct holding the constants of the equation, and the `FunctionValueMesh` struct holding
+/// the values of the functions at each node of a mesh of type `mesh_type` with `num_dimensions` indices.
+fn constants_and_functions(
+    constants: &[(syn::Ident, Option<syn::Expr>)],
+    functions: &[syn::Ident],
+    mesh_type: proc_macro2::TokenStream,
+    num_dimensions: usize,
+) -> proc_macro2::TokenStream {
+    let const_names: Vec<_> = constants.iter().map(|(c, _)| c).collect();
+    let const_args = constants
+        .iter()
+        .filter(|(_, default)| default.is_none())
+        .map(|(c, _)| quote!(#c: f64));
+    let const_inits = constants.iter().map(|(c, default)| match default {
+        Some(default) => quote!(#c: #default),
+        None => quote!(#c),
+    });
+
+    let default_impl = if constants.iter().all(|(_, default)| default.is_some()) {
+        quote!(
+            impl Default for Constants {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+        )
+    } else {
+        quote!()
+    };
+
+    let coordinate_types = (0..num_dimensions).map(|_| quote!(f64));
+    let function_args = functions.iter().map(|f| {
+        let coordinate_types = coordinate_types.clone();
+        quote!(#f: impl Fn(#(#coordinate_types),*) -> f64)
+    });
+    let function_fields = functions.iter().map(|f| quote!(#f: Vec<f64>));
+
+    quote!(
+        #[derive(Clone, Copy, Debug)]
         struct Constants {
-            #consts
+            #(pub #const_names: f64,)*
+        }
+
+        impl Constants {
+            fn new(#(#const_args),*) -> Self {
+                Self {
+                    #(#const_inits,)*
+                }
+            }
         }
 
+        #default_impl
+
+        /// The values of the functions used in the equation at each node of the mesh.
         struct FunctionValueMesh {
-            values: Vec<f64>
+            #(#function_fields,)*
         }
 
         impl FunctionValueMesh {
-            fn new<F: Fn(f64, f64) -> f64>(mesh: &FiniteDiffMesh, #functions) -> Self {
-                todo!()
+            /// Evaluates the functions at the physical coordinates of each node of the mesh.
+            #[allow(unused_variables)]
+            fn new(mesh: &#mesh_type, #(#function_args),*) -> Self {
+                Self {
+                    #(#functions: mesh.evaluate(#functions),)*
+                }
             }
         }
     )
-    .into()
+}
+
+/// Schemes from Taylor tables are in terms of the computational domain, where nodes are spaced by 1. On a
+/// simple grid, the derivative in the physical domain is found by dividing by the spacing for each
+/// variable that the derivative is taken with respect to.
+fn scale_derivative(scheme: MeshExpr, vars: &[Variable]) -> MeshExpr {
+    let mut factors: Vec<_> = [Variable::X, Variable::Y, Variable::Z]
+        .into_iter()
+        .filter_map(|v| {
+            let order = vars.iter().filter(|w| **w == v).count();
+            (order > 0).then(|| {
+                MeshExpr::Reciprocal(Box::new(MeshExpr::Pow(
+                    Box::new(MeshExpr::Spacing(v)),
+                    order as f64,
+                )))
+            })
+        })
+        .collect();
+
+    factors.push(scheme);
+    MeshExpr::Prod(factors)
+}
+
+/// On a rectilinear mesh, the weights of the nodes in a derivative depend on the spacing around each node, so
+/// the scheme uses a [`MeshExpr::Weight`] for each node, which the mesh computes at run time. Derivatives
+/// w.r.t. one variable use the nodes of the stencil on that axis, and mixed derivatives are products of
+/// these, which need the stencil to hold every node they combine. Gives `None` if the stencil doesn't have
+/// enough nodes for the derivative.
+fn nonuniform_derivative(vars: &[Variable], stencil: &[(isize, isize)]) -> Option<MeshExpr> {
+    let x_order = vars.iter().filter(|v| **v == Variable::X).count();
+    let y_order = vars.len() - x_order;
+
+    // The offsets and weights of the nodes on an axis, or the center alone if the derivative isn't taken
+    // w.r.t. that variable
+    let axis = |variable: Variable, order: usize| -> Option<Vec<(isize, MeshExpr)>> {
+        if order == 0 {
+            return Some(vec![(0, MeshExpr::Constant(1.))]);
+        }
+
+        let offsets: Vec<isize> = match variable {
+            Variable::X => stencil
+                .iter()
+                .filter(|(_, j)| *j == 0)
+                .map(|(i, _)| *i)
+                .collect(),
+            Variable::Y => stencil
+                .iter()
+                .filter(|(i, _)| *i == 0)
+                .map(|(_, j)| *j)
+                .collect(),
+            Variable::Z => return None,
+        };
+
+        (offsets.len() > order).then(|| {
+            offsets
+                .into_iter()
+                .map(|offset| (offset, MeshExpr::Weight(variable, order, offset)))
+                .collect()
+        })
+    };
+
+    let x_nodes = axis(Variable::X, x_order)?;
+    let y_nodes = axis(Variable::Y, y_order)?;
+
+    let mut terms = Vec::with_capacity(x_nodes.len() * y_nodes.len());
+    for (i, x_weight) in &x_nodes {
+        for (j, y_weight) in &y_nodes {
+            if !stencil.contains(&(*i, *j)) {
+                return None;
+            }
+
+            terms.push(MeshExpr::Prod(vec![
+                x_weight.clone(),
+                y_weight.clone(),
+                MeshExpr::AtOffset(vec![*i, *j]),
+            ]));
+        }
+    }
+
+    Some(MeshExpr::Sum(terms))
+}
+
+/// On a curvilinear mesh, derivatives w.r.t. the physical coordinates are found from the derivatives w.r.t.
+/// the computational coordinates `p` and `q` with the chain rule, e.g. `u_x = u_p p_x + u_q q_x`. The metric
+/// terms such as `p_x` are read from the mesh at each node. Gives `None` for derivatives above second
+/// order, or if the stencil can't approximate a derivative that the chain rule needs.
+fn chain_rule(
+    vars: &[Variable],
+    computational_derivative: impl Fn(&[Variable]) -> Option<MeshExpr>,
+) -> Option<MeshExpr> {
+    let coordinates = [Variable::X, Variable::Y];
+    let metric = |c: Variable, vars: &[Variable]| MeshExpr::Metric(c, vars.to_vec());
+
+    let mut terms = Vec::new();
+    match *vars {
+        [a] => {
+            for c in coordinates {
+                terms.push(MeshExpr::Prod(vec![
+                    computational_derivative(&[c])?,
+                    metric(c, &[a]),
+                ]));
+            }
+        }
+        [a, b] => {
+            for c in coordinates {
+                for d in coordinates {
+                    let mut computational = [c, d];
+                    computational.sort();
+
+                    terms.push(MeshExpr::Prod(vec![
+                        computational_derivative(&computational)?,
+                        metric(c, &[a]),
+                        metric(d, &[b]),
+                    ]));
+                }
+
+                let mut physical = [a, b];
+                physical.sort();
+                terms.push(MeshExpr::Prod(vec![
+                    computational_derivative(&[c])?,
+                    metric(c, &physical),
+                ]));
+            }
+        }
+        _ => return None,
+    }
+
+    Some(MeshExpr::Sum(terms))
 }
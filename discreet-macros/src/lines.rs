@@ -0,0 +1,215 @@
+use discreet_common::algebra::{MeshExpr, Variable};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Expr, Ident};
+
+/// The placeholder that the derivative w.r.t. time is discretised as, which the equation is then solved for
+/// to give the right hand side of the system of ODEs.
+fn time_derivative() -> MeshExpr {
+    MeshExpr::SymbolicConst(format_ident!("u_t"))
+}
+
+/// The discretisation of a derivative involving time, which can only be the first derivative w.r.t. time on
+/// its own, as the other derivatives are found in space only.
+pub fn discretise_time_derivative(vars: &[Variable], time: Variable) -> Result<MeshExpr, String> {
+    if vars == [time] {
+        Ok(time_derivative())
+    } else {
+        Err("Only the first derivative in time can be used with `time`.".into())
+    }
+}
+
+/// Solves the discretised equation for the derivative w.r.t. time, giving the rate of change of the solution
+/// at a node in terms of the values around it.
+pub fn rate_of_change(residual: &MeshExpr) -> Result<MeshExpr, String> {
+    let u_t = time_derivative();
+
+    if residual.clone().substitute(&u_t, &MeshExpr::Constant(0.)) == *residual {
+        return Err("The equation should contain the first derivative in time.".into());
+    }
+
+    let coefficient = residual.differentiate(&u_t).simplify();
+    if coefficient
+        .clone()
+        .substitute(&u_t, &MeshExpr::Constant(0.))
+        != coefficient
+    {
+        return Err("The equation should be linear in the first derivative in time.".into());
+    }
+
+    Ok(residual.clone().find_root_linear(&u_t).collect_linear())
+}
+
+/// The body of `max_dt`, which evaluates the limit on the time step given in the macro. The limit can use the
+/// constants, and the spacing that `spacing` binds.
+pub fn max_dt_body(limit: &Expr, spacing: TokenStream, constants: &[&Ident]) -> TokenStream {
+    quote! {
+        let Constants { #(#constants),* } = self.consts;
+        #spacing
+
+        #limit
+    }
+}
+
+/// The struct `FiniteDiff` holding a mesh of type `mesh_type` and the current time, which integrates the
+/// system of ODEs given by the spatial discretisation, whose right hand side is computed by `rhs_body`. The
+/// stable time step of the system is computed by `max_dt_body`, if a limit is given. `boundary_setup` can use
+/// the time, named by `time`, and is run again at the time of each stage. `mesh_check` is run on the mesh
+/// given to `new`.
+pub fn integrator(
+    mesh_type: TokenStream,
+    time: &Ident,
+    mesh_check: TokenStream,
+    boundary_setup: TokenStream,
+    rhs_body: TokenStream,
+    max_dt_body: Option<TokenStream>,
+) -> TokenStream {
+    let max_dt = max_dt_body.map(|body| {
+        quote! {
+            #[allow(unused_variables)]
+            fn max_dt(&self) -> f64 {
+                #body
+            }
+        }
+    });
+
+    quote! {
+        struct FiniteDiff {
+            consts: Constants,
+            fns: FunctionValueMesh,
+            mesh: #mesh_type,
+            time: f64
+        }
+
+        impl FiniteDiff {
+            fn new(consts: Constants, mut mesh: #mesh_type, fns: FunctionValueMesh) -> Self {
+                #mesh_check
+                Self::set_boundaries(&mut mesh, consts, 0.);
+                mesh.apply_boundary_conditions();
+
+                Self {
+                    consts,
+                    mesh,
+                    fns,
+                    time: 0.
+                }
+            }
+
+            /// Sets the boundary conditions given in the problem definition on the mesh, with their values at
+            /// the given time.
+            #[allow(unused_variables)]
+            fn set_boundaries(mesh: &mut #mesh_type, consts: Constants, #time: f64) {
+                #boundary_setup
+            }
+
+            /// The time derivative of the solution at every node, given its values `u`, in the order they are
+            /// stored in the mesh. Nodes where the stencil can't be centered get zero, as their values are set
+            /// by the boundary conditions.
+            #[allow(unused_variables)]
+            fn rhs(&self, u: &[f64], du: &mut [f64]) {
+                du.fill(0.);
+
+                #rhs_body
+            }
+
+            /// Integrates from the current time to `end` with steps of size `dt`, saving the solution at each
+            /// of `snapshot_times` that is reached along the way.
+            fn integrate(
+                &mut self,
+                end: f64,
+                dt: f64,
+                scheme: ::discreet_common::timestepping::TimeScheme,
+                snapshot_times: &[f64],
+            ) -> Vec<::discreet_common::timestepping::Snapshot> {
+                let start = self.time;
+                let snapshots = ::discreet_common::timestepping::integrate(
+                    self,
+                    scheme,
+                    start,
+                    end,
+                    dt,
+                    snapshot_times,
+                );
+                self.time = end;
+
+                snapshots
+            }
+
+            /// Integrates from the current time to `end` with steps whose size is chosen to keep the
+            /// estimated error within the tolerance of `control`, saving the solution at each of
+            /// `snapshot_times` that is reached along the way.
+            fn integrate_adaptive(
+                &mut self,
+                end: f64,
+                control: ::discreet_common::timestepping::StepControl,
+                snapshot_times: &[f64],
+            ) -> ::discreet_common::timestepping::AdaptiveReport {
+                let start = self.time;
+                let report = ::discreet_common::timestepping::integrate_adaptive(
+                    self,
+                    control,
+                    start,
+                    end,
+                    snapshot_times,
+                );
+                self.time = end;
+
+                report
+            }
+        }
+
+        impl ::discreet_common::timestepping::MethodOfLines for FiniteDiff {
+            fn state(&self) -> &[f64] {
+                self.mesh.values()
+            }
+
+            fn set_state(&mut self, time: f64, u: &[f64]) {
+                self.mesh.set_values(u);
+                Self::set_boundaries(&mut self.mesh, self.consts, time);
+                self.mesh.apply_boundary_conditions();
+            }
+
+            fn rhs(&self, u: &[f64], du: &mut [f64]) {
+                FiniteDiff::rhs(self, u, du);
+            }
+
+            #max_dt
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use discreet_common::algebra::MeshExpr;
+    use quote::format_ident;
+
+    use super::{rate_of_change, time_derivative};
+
+    #[test]
+    fn rates() {
+        let nu = MeshExpr::SymbolicConst(format_ident!("nu"));
+        let diffusion = MeshExpr::Prod(vec![nu.clone(), MeshExpr::AtOffset(vec![1])]);
+
+        // u_t - nu u(i + 1) = 0
+        let residual = MeshExpr::Sum(vec![
+            time_derivative(),
+            MeshExpr::Negate(Box::new(diffusion.clone())),
+        ]);
+        let rate = rate_of_change(&residual).unwrap();
+        assert_eq!(
+            rate.evaluate(&|leaf| match leaf {
+                MeshExpr::SymbolicConst(_) => 2.,
+                _ => 3.,
+            }),
+            6.
+        );
+
+        assert!(rate_of_change(&diffusion).is_err());
+
+        let nonlinear = MeshExpr::Sum(vec![
+            MeshExpr::Pow(Box::new(time_derivative()), 2.),
+            diffusion,
+        ]);
+        assert!(rate_of_change(&nonlinear).is_err());
+    }
+}
@@ -7,11 +7,8 @@ fn main() {
         (-(x - 3.).powi(2)).exp()
     });
 
-    let mut method = FiniteDiff::new(
-        Constants { c: 0.5 },
-        mesh,
-        FunctionValueMesh { values: Vec::new() },
-    );
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::new(0.5), mesh, fns);
 
     method.run_iteration();
 
@@ -23,7 +20,8 @@ fn main() {
 }
 
 finite_diff_2d! {
-    equation: u_y + c * u_x = 0,
+    dimensions: (x, t),
+    equation: u_t + c * u_x = 0,
     stencil: [(-1, 0), (0, 0), (0, -1)],
     constants: [c],
     functions: [],
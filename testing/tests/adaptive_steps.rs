@@ -0,0 +1,44 @@
+use std::f64::consts::PI;
+
+use discreet_common::{
+    mesh1d::FiniteDiffMesh1D,
+    timestepping::{EmbeddedScheme, MethodOfLines, StepControl},
+};
+use discreet_macros::finite_diff_1d;
+
+finite_diff_1d! {
+    time: t,
+    equation: u_t = nu * u_xx,
+    stencil: [-1, 0, 1],
+    max_dt: 0.5 * dx * dx / nu,
+    constants: [nu = 0.1],
+    boundaries: { left: dirichlet(0.0), right: dirichlet(0.0) },
+}
+
+#[test]
+fn heat_with_dormand_prince() {
+    let mut mesh = FiniteDiffMesh1D::from_num_points(0., 1., 41);
+    let initial = mesh.evaluate(|x| (PI * x).sin());
+    mesh.set_values(&initial);
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    let max_dt = method.max_dt();
+    assert!((max_dt - 0.5 * 0.025 * 0.025 / 0.1).abs() < 1e-15);
+
+    let control = StepControl::new(EmbeddedScheme::DormandPrince, 1e-8, 1e-4);
+    let report = method.integrate_adaptive(1., control, &[1.]);
+
+    // The smooth solution allows long steps, so the stability limit is what holds them back
+    assert!(report.dt_history.iter().all(|&dt| dt <= max_dt));
+    assert!(report.dt_history.contains(&max_dt));
+
+    let decay = (-PI * PI * 0.1).exp();
+    let max_error = report.snapshots[0]
+        .values
+        .iter()
+        .zip(&initial)
+        .map(|(u, u0)| (u - decay * u0).abs())
+        .fold(0., f64::max);
+    assert!(max_error < 5e-4, "max error {max_error}");
+}
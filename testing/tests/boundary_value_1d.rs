@@ -0,0 +1,30 @@
+use std::f64::consts::FRAC_PI_2;
+
+use discreet_common::mesh1d::FiniteDiffMesh1D;
+use discreet_macros::finite_diff_1d;
+
+// The example from the documentation, solved as a tridiagonal system
+finite_diff_1d! {
+    equation: u_xx = f,
+    stencil: [-1, 0, 1],
+    unknown: [-1, 0, 1],
+    functions: [f],
+    boundaries: { left: dirichlet(0.0), right: neumann(0.0) },
+}
+
+#[test]
+fn insulated_end() {
+    let n = 41;
+    let mesh = FiniteDiffMesh1D::from_num_points(0., 1., n);
+    let fns = FunctionValueMesh::new(&mesh, |x| -FRAC_PI_2 * FRAC_PI_2 * (FRAC_PI_2 * x).sin());
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+    method.run_iteration();
+
+    // sin(pi x / 2) has no slope at x = 1
+    for i in method.mesh.index_iter() {
+        let x = method.mesh.coordinate(i);
+        let error = (method.mesh.get_at(i) - (FRAC_PI_2 * x).sin()).abs();
+
+        assert!(error < 5e-4, "error {error} at x = {x}");
+    }
+}
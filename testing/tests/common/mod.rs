@@ -0,0 +1,25 @@
+//! The Poisson problem `u_xx + u_yy = f` on the unit square that several of the tests solve. Its solution is
+//! zero on the boundary, which is what meshes start with, so the schemes need no boundary conditions.
+
+// Each test crate uses only some of these
+#![allow(dead_code)]
+
+use std::f64::consts::PI;
+
+pub fn exact(x: f64, y: f64) -> f64 {
+    (PI * x).sin() * (PI * y).sin()
+}
+
+/// The source term `f` that gives [`exact`].
+pub fn source(x: f64, y: f64) -> f64 {
+    -2. * PI * PI * exact(x, y)
+}
+
+/// The largest difference between the values at the nodes and the expected ones, given in the same order.
+pub fn max_error(values: &[f64], expected: &[f64]) -> f64 {
+    values
+        .iter()
+        .zip(expected)
+        .map(|(u, e)| (u - e).abs())
+        .fold(0., f64::max)
+}
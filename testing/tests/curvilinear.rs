@@ -0,0 +1,89 @@
+use std::f64::consts::PI;
+
+use discreet_common::mesh2d::{FiniteDiffMesh, PhysicalCoordinate};
+
+mod common;
+
+/// The unit square with the interior nodes moved off the axes, so the mesh lines are curved.
+fn distorted_square(n: usize) -> FiniteDiffMesh {
+    let h = 1. / (n - 1) as f64;
+    let lines: Vec<Vec<PhysicalCoordinate>> = (0..n)
+        .map(|j| {
+            (0..n)
+                .map(|i| {
+                    let (s, r) = (i as f64 * h, j as f64 * h);
+                    PhysicalCoordinate::new(
+                        s + 0.05 * (2. * PI * s).sin() * (PI * r).sin(),
+                        r + 0.05 * (PI * s).sin() * (2. * PI * r).sin(),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    let lines: Vec<&[PhysicalCoordinate]> = lines.iter().map(Vec::as_slice).collect();
+
+    FiniteDiffMesh::from_physical_domain(&lines)
+}
+
+mod nine_point {
+    use discreet_common::{
+        iterative::IterativeMethod,
+        mesh2d::{FiniteDiffMesh, MeshScaling},
+    };
+    use discreet_macros::finite_diff_2d;
+
+    use super::{common, distorted_square};
+
+    finite_diff_2d! {
+        equation: u_xx + u_yy = f,
+        stencil: [(-1, -1), (0, -1), (1, -1), (-1, 0), (0, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+        functions: [f],
+    }
+
+    #[test]
+    fn poisson_on_curved_mesh() {
+        let mesh = distorted_square(21);
+        let fns = FunctionValueMesh::new(&mesh, common::source);
+        let expected = mesh.evaluate(common::exact);
+        let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+        let report = method.solve(1e-8, 5000, IterativeMethod::Sor(1.5));
+        assert!(report.converged, "residual {}", report.final_residual());
+
+        let max_error = common::max_error(method.mesh.values(), &expected);
+        assert!(max_error < 5e-3, "max error {max_error}");
+    }
+}
+
+mod five_point {
+    use discreet_common::mesh2d::{FiniteDiffMesh, MeshScaling};
+    use discreet_macros::finite_diff_2d;
+
+    use super::distorted_square;
+
+    // Without the mixed derivative, the chain rule can't be formed, so there is no curvilinear scheme
+    finite_diff_2d! {
+        equation: u_xx + u_yy = 0,
+        stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+        boundaries: { left: dirichlet(1) },
+    }
+
+    #[test]
+    #[should_panic(expected = "can't approximate u_xx, u_yy on a curvilinear mesh")]
+    fn curved_mesh_rejected() {
+        let mesh = distorted_square(11);
+        let fns = FunctionValueMesh::new(&mesh);
+        FiniteDiff::new(Constants::new(), mesh, fns);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't approximate u_xx, u_yy on a curvilinear mesh")]
+    fn curved_mesh_swapped_in() {
+        let mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., 11, 11);
+        let fns = FunctionValueMesh::new(&mesh);
+        let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+        method.mesh = distorted_square(11);
+        method.run_iteration();
+    }
+}
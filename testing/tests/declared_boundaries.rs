@@ -0,0 +1,33 @@
+use std::f64::consts::FRAC_PI_2;
+
+use discreet_common::mesh2d::{FiniteDiffMesh, MeshScaling};
+use discreet_macros::finite_diff_2d;
+
+// The heat equation from the documentation, with the initial values, a fixed end and an insulated end all
+// declared in the macro
+finite_diff_2d! {
+    dimensions: (x, t),
+    equation: u_t = nu * u_xx,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1)],
+    unknown: [(-1, 0), (0, 0), (1, 0)],
+    constants: [nu = 0.5],
+    boundaries: { bottom: dirichlet(x.sin()), left: dirichlet(0.0), right: neumann(0.0) },
+}
+
+#[test]
+fn insulated_end() {
+    let (nx, nt, end) = (41, 401, 0.5);
+    let mesh = FiniteDiffMesh::from_num_points(0., FRAC_PI_2, 0., end, nx, nt);
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    method.run_iteration();
+
+    // sin(x) decays without changing shape, as it has no slope at the insulated end
+    let decay = (-0.5 * end).exp();
+    for i in 0..nx {
+        let x = FRAC_PI_2 * i as f64 / (nx - 1) as f64;
+        let error = (method.mesh.get_at(i, nt - 1) - decay * x.sin()).abs();
+
+        assert!(error < 1e-3, "error {error} at x = {x}");
+    }
+}
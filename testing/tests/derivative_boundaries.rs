@@ -0,0 +1,80 @@
+use std::f64::consts::PI;
+
+use discreet_common::{
+    iterative::IterativeMethod,
+    mesh2d::{Boundary, BoundaryCondition, FiniteDiffMesh, MeshScaling},
+};
+use discreet_macros::finite_diff_2d;
+
+finite_diff_2d! {
+    equation: u_xx + u_yy = 0,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+}
+
+fn max_error<F: Fn(f64, f64) -> f64>(mesh: &FiniteDiffMesh, exact: F) -> f64 {
+    let exact = mesh.evaluate(exact);
+    mesh.values()
+        .iter()
+        .zip(exact)
+        .map(|(u, e)| (u - e).abs())
+        .fold(0., f64::max)
+}
+
+#[test]
+fn neumann_sides() {
+    // Harmonic, with du/dn = 1 on the left and right
+    let exact = |x: f64, y: f64| (PI * x).cos() * (PI * y).cosh() / PI.cosh() + x;
+
+    let n = 21;
+    let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., n, n);
+    mesh.set_boundary_condition(Boundary::Left, BoundaryCondition::Neumann(-1.));
+    mesh.set_boundary_condition(Boundary::Right, BoundaryCondition::Neumann(1.));
+    mesh.set_dirichlet(Boundary::Bottom, exact);
+    mesh.set_dirichlet(Boundary::Top, exact);
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+    let report = method.solve(1e-10, 5000, IterativeMethod::Sor(1.7));
+    assert!(report.converged, "residual {}", report.final_residual());
+
+    let max_error = max_error(&method.mesh, exact);
+    assert!(max_error < 5e-3, "max error {max_error}");
+}
+
+#[test]
+fn robin_sides() {
+    // u = 1 + x satisfies 2u + du/dn = 1 on the left and u + du/dn = 3 on the right
+    let exact = |x: f64, _| 1. + x;
+
+    let n = 11;
+    let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., n, n);
+    mesh.set_boundary_condition(
+        Boundary::Left,
+        BoundaryCondition::Robin {
+            a: 2.,
+            b: 1.,
+            g: 1.,
+        },
+    );
+    mesh.set_boundary_condition(
+        Boundary::Right,
+        BoundaryCondition::Robin {
+            a: 1.,
+            b: 1.,
+            g: 3.,
+        },
+    );
+    mesh.set_boundary_condition(Boundary::Bottom, BoundaryCondition::Neumann(0.));
+    mesh.set_boundary_condition(Boundary::Top, BoundaryCondition::Neumann(0.));
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+    let report = method.solve(1e-12, 10000, IterativeMethod::Sor(1.5));
+    assert!(report.converged, "residual {}", report.final_residual());
+
+    // The differences are exact for a linear solution
+    let max_error = max_error(&method.mesh, exact);
+    assert!(max_error < 1e-8, "max error {max_error}");
+}
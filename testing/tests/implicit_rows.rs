@@ -0,0 +1,36 @@
+use std::f64::consts::PI;
+
+use discreet_common::mesh2d::{Boundary, FiniteDiffMesh, MeshScaling};
+use discreet_macros::finite_diff_2d;
+
+// Backward Euler for the heat equation, which solves a tridiagonal system for each time level
+finite_diff_2d! {
+    dimensions: (x, t),
+    equation: u_t = nu * u_xx,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1)],
+    unknown: [(-1, 0), (0, 0), (1, 0)],
+    constants: [nu = 1],
+}
+
+#[test]
+fn backward_euler_diffusion() {
+    let (nx, nt, end) = (41, 401, 0.1);
+    let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., end, nx, nt);
+    mesh.fill_dirichlet_bc_vals(Boundary::Bottom, |x| (PI * x).sin());
+    mesh.set_dirichlet(Boundary::Left, |_, _| 0.);
+    mesh.set_dirichlet(Boundary::Right, |_, _| 0.);
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    method.run_iteration();
+
+    let decay = (-PI * PI * end).exp();
+    for i in 0..nx {
+        let x = i as f64 / (nx - 1) as f64;
+        let exact = decay * (PI * x).sin();
+        let error = (method.mesh.get_at(i, nt - 1) - exact).abs();
+
+        // First order in time and second order in space
+        assert!(error < 1.5e-3, "error {error} at x = {x}");
+    }
+}
@@ -0,0 +1,42 @@
+use discreet_common::{
+    iterative::IterativeMethod,
+    mesh2d::{FiniteDiffMesh, MeshScaling},
+};
+use discreet_macros::finite_diff_2d;
+
+mod common;
+
+finite_diff_2d! {
+    equation: u_xx + u_yy = f,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+    functions: [f],
+}
+
+#[test]
+fn poisson_by_each_method() {
+    let mut iterations = Vec::new();
+    for method in [
+        IterativeMethod::Jacobi,
+        IterativeMethod::GaussSeidel,
+        IterativeMethod::Sor(1.7),
+    ] {
+        let mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., 17, 17);
+        let fns = FunctionValueMesh::new(&mesh, common::source);
+        let expected = mesh.evaluate(common::exact);
+        let mut scheme = FiniteDiff::new(Constants::new(), mesh, fns);
+
+        let report = scheme.solve(1e-8, 5000, method);
+        assert!(
+            report.converged,
+            "{method:?} residual {}",
+            report.final_residual()
+        );
+        iterations.push(report.iterations);
+
+        let max_error = common::max_error(scheme.mesh.values(), &expected);
+        assert!(max_error < 5e-3, "{method:?} max error {max_error}");
+    }
+
+    // Each method converges faster than the one before
+    assert!(iterations.windows(2).all(|w| w[1] < w[0]), "{iterations:?}");
+}
@@ -0,0 +1,29 @@
+use discreet_common::{
+    iterative::IterativeMethod,
+    mesh2d::{FiniteDiffMesh, MeshScaling},
+};
+use discreet_macros::finite_diff_2d;
+
+mod common;
+
+finite_diff_2d! {
+    equation: u_xx + u_yy = f,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+    unknown: [(-1, 0), (0, 0), (1, 0)],
+    functions: [f],
+}
+
+#[test]
+fn poisson_by_line_relaxation() {
+    let mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., 21, 21);
+    let fns = FunctionValueMesh::new(&mesh, common::source);
+    let expected = mesh.evaluate(common::exact);
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+    let report = method.solve(1e-8, 1000, IterativeMethod::Sor(1.5));
+    assert!(report.converged, "residual {}", report.final_residual());
+
+    // The five point Laplacian is second order accurate
+    let max_error = common::max_error(method.mesh.values(), &expected);
+    assert!(max_error < 5e-3, "max error {max_error}");
+}
@@ -0,0 +1,42 @@
+use std::f64::consts::PI;
+
+use discreet_common::{
+    mesh2d::{FiniteDiffMesh, MeshScaling},
+    timestepping::TimeScheme,
+};
+use discreet_macros::finite_diff_2d;
+
+mod common;
+
+finite_diff_2d! {
+    time: t,
+    equation: u_t = nu * (u_xx + u_yy),
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+    constants: [nu = 0.1],
+}
+
+#[test]
+fn heat_with_rk4() {
+    let n = 21;
+    let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., 1., n, n);
+    let initial = mesh.evaluate(common::exact);
+    mesh.set_values(&initial);
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    let snapshots = method.integrate(0.5, 2e-3, TimeScheme::Rk4, &[0.25, 0.5]);
+
+    assert_eq!(snapshots.len(), 2);
+    for snapshot in snapshots {
+        let decay = (-2. * PI * PI * 0.1 * snapshot.time).exp();
+        let expected: Vec<f64> = initial.iter().map(|u0| decay * u0).collect();
+        let max_error = common::max_error(&snapshot.values, &expected);
+
+        // The error is that of the five point Laplacian, as RK4 is accurate to far below it
+        assert!(
+            max_error < 2e-3,
+            "max error {max_error} at t = {}",
+            snapshot.time
+        );
+    }
+}
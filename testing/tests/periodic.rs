@@ -0,0 +1,40 @@
+use std::f64::consts::PI;
+
+use discreet_common::mesh2d::{Boundary, FiniteDiffMesh, MeshScaling};
+use discreet_macros::finite_diff_2d;
+
+// Backward Euler for the heat equation, with the rows wrapping around a periodic x boundary
+finite_diff_2d! {
+    dimensions: (x, t),
+    equation: u_t = nu * u_xx,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1)],
+    unknown: [(-1, 0), (0, 0), (1, 0)],
+    constants: [nu = 0.1],
+    boundaries: { bottom: dirichlet((2. * PI * x).sin()) },
+}
+
+#[test]
+fn periodic_implicit_diffusion() {
+    let (nx, nt, end) = (41, 201, 0.1);
+    let mut mesh = FiniteDiffMesh::from_num_points(0., 1., 0., end, nx, nt);
+    mesh.set_periodic(Boundary::Left);
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    method.run_iteration();
+
+    let decay = (-4. * PI * PI * 0.1 * end).exp();
+    for i in 0..nx {
+        let x = i as f64 / (nx - 1) as f64;
+        let exact = decay * (2. * PI * x).sin();
+        let error = (method.mesh.get_at(i, nt - 1) - exact).abs();
+
+        assert!(error < 1e-3, "error {error} at x = {x}");
+    }
+
+    // The last node is the same point as the first
+    assert_eq!(
+        method.mesh.get_at(nx - 1, nt - 1),
+        method.mesh.get_at(0, nt - 1)
+    );
+}
@@ -0,0 +1,41 @@
+use discreet_common::{
+    grid::Stretching,
+    iterative::IterativeMethod,
+    mesh2d::{FiniteDiffMesh, MeshScaling},
+};
+use discreet_macros::finite_diff_2d;
+
+mod common;
+
+finite_diff_2d! {
+    equation: u_xx + u_yy = f,
+    stencil: [(-1, 0), (0, 0), (1, 0), (0, -1), (0, 1)],
+    functions: [f],
+}
+
+/// The largest error of the Poisson solution on a mesh with `n` nodes along each side, clustered towards the
+/// sides.
+fn max_error(n: usize) -> f64 {
+    let mesh = FiniteDiffMesh::from_coordinates(
+        Stretching::Tanh(2.).distribute(n),
+        Stretching::Tanh(1.).distribute(n),
+    );
+    let fns = FunctionValueMesh::new(&mesh, common::source);
+    let expected = mesh.evaluate(common::exact);
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+
+    let report = method.solve(1e-10, 10000, IterativeMethod::Sor(1.7));
+    assert!(report.converged, "residual {}", report.final_residual());
+
+    common::max_error(method.mesh.values(), &expected)
+}
+
+#[test]
+fn poisson_on_stretched_mesh() {
+    let coarse = max_error(11);
+    let fine = max_error(21);
+
+    assert!(coarse < 1e-2, "max error {coarse}");
+    // Halving the spacing divides the error by about four, so the uneven spacing keeps second order accuracy
+    assert!(coarse / fine > 3.5, "errors {coarse} and {fine}");
+}
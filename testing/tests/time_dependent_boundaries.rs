@@ -0,0 +1,28 @@
+use discreet_common::{mesh1d::FiniteDiffMesh1D, timestepping::TimeScheme};
+use discreet_macros::finite_diff_1d;
+
+// u = t + x^2 / 2 solves the heat equation, and is matched by the boundary values at every time
+finite_diff_1d! {
+    time: t,
+    equation: u_t = u_xx,
+    stencil: [-1, 0, 1],
+    boundaries: { left: dirichlet(t), right: dirichlet(t + 0.5) },
+}
+
+#[test]
+fn boundary_values_follow_the_stage_time() {
+    let mut mesh = FiniteDiffMesh1D::from_num_points(0., 1., 11);
+    mesh.set_values(&mesh.evaluate(|x| x * x / 2.));
+
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::new(), mesh, fns);
+    method.integrate(0.1, 1e-3, TimeScheme::Rk4, &[]);
+
+    // The solution is quadratic in space and linear in time, so the only error is rounding
+    for i in method.mesh.index_iter() {
+        let x = method.mesh.coordinate(i);
+        let error = (method.mesh.get_at(i) - (0.1 + x * x / 2.)).abs();
+
+        assert!(error < 1e-10, "error {error} at x = {x}");
+    }
+}
@@ -0,0 +1,37 @@
+use std::f64::consts::PI;
+
+use discreet_common::mesh3d::FiniteDiffMesh3D;
+use discreet_macros::finite_diff_3d;
+
+// Forward Euler for the heat equation on a square plate, marching through the layers in time
+finite_diff_3d! {
+    dimensions: (x, y, t),
+    equation: u_t - alpha * (u_xx + u_yy) = 0,
+    stencil: [(0, 0, 0), (-1, 0, -1), (0, 0, -1), (1, 0, -1), (0, -1, -1), (0, 1, -1)],
+    constants: [alpha = 0.1],
+    boundaries: {
+        back: dirichlet((PI * x).sin() * (PI * y).sin()),
+        left: dirichlet(0),
+        right: dirichlet(0),
+        bottom: dirichlet(0),
+        top: dirichlet(0),
+    },
+}
+
+#[test]
+fn plate_cooling() {
+    let (n, nt, end) = (11, 51, 0.5);
+    let mesh = FiniteDiffMesh3D::from_num_points([0., 0., 0.], [1., 1., end], [n, n, nt]);
+    let fns = FunctionValueMesh::new(&mesh);
+    let mut method = FiniteDiff::new(Constants::default(), mesh, fns);
+    method.run_iteration();
+
+    let decay = (-2. * PI * PI * 0.1 * end).exp();
+    for (i, j, k) in method.mesh.index_iter().filter(|&(.., k)| k == nt - 1) {
+        let [x, y, _] = method.mesh.coordinates(i, j, k);
+        let exact = decay * (PI * x).sin() * (PI * y).sin();
+        let error = (method.mesh.get_at(i, j, k) - exact).abs();
+
+        assert!(error < 2e-3, "error {error} at ({x}, {y})");
+    }
+}